@@ -1,18 +1,289 @@
-use crate::protocol::ResponseMessage;
-use anyhow::{anyhow, Result};
-use serde::Serialize;
+use crate::framing::{ContentLength, Framing};
+#[cfg(feature = "metrics")]
+use crate::metrics::{Metrics, MetricsSnapshot};
+use crate::protocol::{
+    CompletionContext, CompletionList, CompletionTriggerKind, InitializeResult, LspErrorCode,
+    NotificationMessage, Position, PositionEncoding, ProgressParams, ProgressToken, RequestId,
+    RequestMessage, ResponseError, ResponseMessage, ServerCapabilities, ShowDocumentResult,
+    TextDocumentContentChangeEvent, TextDocumentItem, TextDocumentSyncKind, WorkDoneProgress,
+    WorkDoneProgressCreateParams,
+};
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fmt::Debug;
+use std::future::{poll_fn, Future};
 use std::pin::Pin;
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use std::process::{ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::net::{TcpStream, UnixStream};
+use tokio::process::{Child, Command};
+use tokio::runtime::Handle;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio_stream::wrappers::UnboundedReceiverStream;
 
 pub trait AsyncReadWrite: AsyncRead + AsyncWrite + Unpin {}
 impl<T: AsyncRead + AsyncWrite + Unpin + ?Sized> AsyncReadWrite for T {}
 
 type Stream = Pin<Box<dyn AsyncReadWrite + Send>>;
 
-pub struct LspClient {
-    stream: Stream,
+/// Answers a request sent by the server, given its params.
+pub type RequestHandler =
+    Box<dyn Fn(serde_json::Value) -> Result<serde_json::Value, ResponseError> + Send + Sync>;
+
+type HandlerResult = Result<serde_json::Value, ResponseError>;
+
+type HandlerFuture = Pin<Box<dyn Future<Output = HandlerResult> + Send>>;
+
+/// Answers a request sent by the server, given its params, until the server cancels it.
+pub type CancellableRequestHandler =
+    Box<dyn Fn(serde_json::Value, CancellationToken) -> HandlerFuture + Send + Sync>;
+
+/// Tells a handler registered with `on_request_cancellable` that the server sent a
+/// `$/cancelRequest` for the request it is answering.
+#[derive(Debug, Clone)]
+pub struct CancellationToken(watch::Receiver<bool>);
+
+impl CancellationToken {
+    pub fn is_cancelled(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    /// Resolves once the request is cancelled; never resolves if it is not.
+    pub async fn cancelled(&self) {
+        let mut cancelled = self.0.clone();
+        if cancelled.wait_for(|cancelled| *cancelled).await.is_err() {
+            // The request was answered, so it can no longer be cancelled.
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+/// A server request being answered by a cancellable handler.
+struct InboundRequest {
+    id: serde_json::Value,
+    cancel: watch::Sender<bool>,
+    answer: HandlerFuture,
+}
+
+/// Polls the handlers answering server requests, returning the first answer that is ready.
+fn poll_inbound(
+    inbound_requests: &mut HashMap<RequestId, InboundRequest>,
+    cx: &mut Context<'_>,
+) -> Poll<(serde_json::Value, HandlerResult)> {
+    let ready = inbound_requests.iter_mut().find_map(|(id, request)| {
+        match request.answer.as_mut().poll(cx) {
+            Poll::Ready(answer) => Some((id.clone(), answer)),
+            Poll::Pending => None,
+        }
+    });
+    match ready.and_then(|(id, answer)| Some((inbound_requests.remove(&id)?.id, answer))) {
+        Some(answer) => Poll::Ready(answer),
+        None => Poll::Pending,
+    }
+}
+
+/// The error returned for requests that cannot complete because the client is shutting down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShuttingDown;
+
+impl std::fmt::Display for ShuttingDown {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The LSP client is shutting down")
+    }
+}
+
+impl std::error::Error for ShuttingDown {}
+
+/// The error returned when a response arrives for a different request than the one awaited.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseIdMismatch {
+    pub expected: RequestId,
+    pub actual: Option<serde_json::Value>,
+}
+
+impl std::fmt::Display for ResponseIdMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.actual {
+            Some(actual) => write!(
+                f,
+                "Expected a response to request {}, got one for {}",
+                self.expected, actual
+            ),
+            None => write!(
+                f,
+                "Expected a response to request {}, got one without an id",
+                self.expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ResponseIdMismatch {}
+
+/// A message exchanged with the server, as recorded by `LspClient::with_message_log`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LoggedMessage {
+    pub direction: Direction,
+    pub message: serde_json::Value,
+}
+
+/// Whether a logged message was sent to or received from the server.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// A request sent with `LspClient::send_request_async` whose response has not been read.
+/// Pass it to `LspClient::join_responses` to collect the response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseHandle {
+    id: RequestId,
+}
+
+impl ResponseHandle {
+    /// The id of the request this handle waits for.
+    pub fn id(&self) -> &RequestId {
+        &self.id
+    }
+}
+
+/// The response to a request whose result was streamed with a partial result token.
+#[derive(Debug)]
+pub struct PartialResultResponse {
+    /// The chunks received via `$/progress`, in arrival order.
+    pub partial_results: Vec<serde_json::Value>,
+    /// The final response, which carries the remainder of the result.
+    pub response: ResponseMessage,
+}
+
+impl PartialResultResponse {
+    /// Concatenates the streamed chunks and the final result into a single list.
+    /// This applies to requests whose results are arrays, like references or document symbols.
+    pub fn assemble(&self) -> Result<Vec<serde_json::Value>> {
+        if let Some(error) = &self.response.error {
+            return Err(error.clone().into());
+        }
+
+        let mut assembled = Vec::new();
+        let final_result = self.response.result.iter();
+        for chunk in self.partial_results.iter().chain(final_result) {
+            match chunk {
+                serde_json::Value::Array(items) => assembled.extend(items.iter().cloned()),
+                serde_json::Value::Null => {}
+                other => return Err(anyhow!("Expected an array result chunk, got {}", other)),
+            }
+        }
+        Ok(assembled)
+    }
+}
+
+/// Requests the server may send which are answered with an empty result
+/// when no handler has been registered for them.
+const AUTO_ACKNOWLEDGED_REQUESTS: &[&str] = &[
+    "workspace/semanticTokens/refresh",
+    "workspace/codeLens/refresh",
+    "workspace/inlayHint/refresh",
+    "workspace/diagnostic/refresh",
+    "window/workDoneProgress/create",
+];
+
+/// Configures the liveness check performed by `LspClient::check_liveness`.
+#[derive(Debug, Clone)]
+pub struct Heartbeat {
+    /// How long the connection may be idle before a probe is sent.
+    pub interval: Duration,
+    /// How long to wait for the server to answer a probe.
+    pub timeout: Duration,
+    /// The method of the probe request. The default is a `$/` method, which servers answer
+    /// with a `MethodNotFound` error without any side effects; any answer counts as alive.
+    pub method: String,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Heartbeat {
+            interval: Duration::from_secs(30),
+            timeout: Duration::from_secs(10),
+            method: "$/lsp-client-rs/ping".to_string(),
+        }
+    }
+}
+
+/// How `LspClient::initialize` waits for a server that is slow to start.
+#[derive(Debug, Clone)]
+pub struct InitializeOptions {
+    /// How long to wait for each `initialize` attempt to be answered.
+    pub timeout: Duration,
+    /// How many more times to send `initialize` after an attempt timed out or failed.
+    pub retries: u32,
+}
+
+impl Default for InitializeOptions {
+    fn default() -> Self {
+        InitializeOptions {
+            timeout: Duration::from_secs(60),
+            retries: 0,
+        }
+    }
+}
+
+/// How many unread notifications a subscriber can fall behind before it starts missing them.
+const NOTIFICATION_CAPACITY: usize = 256;
+
+pub struct LspClient<F: Framing = ContentLength> {
+    /// Reads are buffered so framings can parse headers without a syscall per byte.
+    stream: BufReader<Stream>,
+    framing: F,
+    pending: HashMap<RequestId, PendingRequest>,
+    notifications: broadcast::Sender<NotificationMessage>,
+    /// The most recent notifications, replayed to every new subscriber.
+    notification_replay: VecDeque<NotificationMessage>,
+    notification_replay_depth: usize,
+    /// With a replay depth, every subscriber gets its own channel seeded with the replay.
+    replay_subscribers: Mutex<Vec<broadcast::Sender<NotificationMessage>>>,
+    server_requests: broadcast::Sender<RequestMessage>,
+    orphaned_responses: broadcast::Sender<ResponseMessage>,
+    closed: watch::Sender<bool>,
+    handlers: HashMap<String, RequestHandler>,
+    cancellable_handlers: HashMap<String, CancellableRequestHandler>,
+    /// Server requests being answered by a cancellable handler, by id.
+    inbound_requests: HashMap<RequestId, InboundRequest>,
+    initialize_request: Option<serde_json::Value>,
+    open_documents: BTreeMap<String, TextDocumentItem>,
+    next_id: u32,
+    shutting_down: bool,
+    partial_results: HashMap<ProgressToken, Vec<serde_json::Value>>,
+    progress_tokens: HashSet<ProgressToken>,
+    ended_progress: HashSet<ProgressToken>,
+    progress_percentages: HashMap<ProgressToken, u32>,
+    child: Option<Child>,
+    stderr: Option<mpsc::UnboundedReceiver<String>>,
+    pretty_logging: bool,
+    message_log: Option<Vec<LoggedMessage>>,
+    heartbeat: Option<Heartbeat>,
+    initialize_options: InitializeOptions,
+    content_modified_retries: u32,
+    position_encoding: PositionEncoding,
+    text_document_sync: TextDocumentSyncKind,
+    last_activity: Instant,
+    #[cfg(feature = "metrics")]
+    metrics: Metrics,
+}
+
+/// A request that has been sent but not yet answered.
+struct PendingRequest {
+    method: String,
+    sent_at: Instant,
+    /// The document the request is about, if any.
+    uri: Option<String>,
+    /// Whether a `$/cancelRequest` was sent for it.
+    cancelled: bool,
 }
 
 impl LspClient {
@@ -25,8 +296,8 @@ impl LspClient {
             "tcp" => {
                 // Skip the scheme part and rejoin the rest (address and port)
                 let addr = addr
-                    .splitn(2, ':')
-                    .nth(1)
+                    .split_once(':')
+                    .map(|(_, rest)| rest)
                     .ok_or(anyhow!("Invalid TCP address format."))?;
                 let tcp_stream = TcpStream::connect(addr).await?;
                 Box::pin(tcp_stream) as Stream
@@ -34,8 +305,8 @@ impl LspClient {
             "unix" => {
                 // Skip the scheme part for UNIX domain socket path
                 let path = addr
-                    .splitn(2, ':')
-                    .nth(1)
+                    .split_once(':')
+                    .map(|(_, rest)| rest)
                     .ok_or(anyhow!("Invalid UNIX socket path format."))?;
                 let unix_stream = UnixStream::connect(path).await?;
                 Box::pin(unix_stream) as Stream
@@ -48,120 +319,3135 @@ impl LspClient {
             }
         };
 
-        Ok(Self { stream })
+        Ok(Self::from_boxed(stream))
+    }
+
+    /// Creates a client over an already connected stream.
+    /// This allows supplying a custom transport such as an in-memory pipe or a TLS stream.
+    pub fn from_stream(stream: impl AsyncReadWrite + Send + 'static) -> Self {
+        Self::from_boxed(Box::pin(stream))
+    }
+
+    /// Spawns a server process and talks to it over its stdin and stdout.
+    /// The server's stderr is captured line by line, see `server_stderr`.
+    /// The process is killed when the client is dropped.
+    /// Must be called from within a tokio runtime, use `spawn_on` otherwise.
+    pub fn spawn(command: Command) -> Result<Self> {
+        Self::spawn_on(command, &Handle::current())
+    }
+
+    /// Like `spawn`, but registers the process and the task capturing its stderr with the
+    /// runtime behind `handle`, so it can be called from outside of a runtime.
+    /// Apart from that task the client spawns nothing; it is driven by whoever awaits it.
+    pub fn spawn_on(mut command: Command, handle: &Handle) -> Result<Self> {
+        let _runtime = handle.enter();
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| anyhow!("Failed to open the server's stdin"))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| anyhow!("Failed to open the server's stdout"))?;
+        let stderr = child
+            .stderr
+            .take()
+            .ok_or_else(|| anyhow!("Failed to open the server's stderr"))?;
+
+        let (stderr_tx, stderr_rx) = mpsc::unbounded_channel();
+        handle.spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if stderr_tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut client = Self::from_stream(tokio::io::join(stdout, stdin));
+        client.child = Some(child);
+        client.stderr = Some(stderr_rx);
+        Ok(client)
+    }
+
+    fn from_boxed(stream: Stream) -> Self {
+        let (notifications, _) = broadcast::channel(NOTIFICATION_CAPACITY);
+        let (server_requests, _) = broadcast::channel(NOTIFICATION_CAPACITY);
+        let (orphaned_responses, _) = broadcast::channel(NOTIFICATION_CAPACITY);
+        let (closed, _) = watch::channel(false);
+        Self {
+            stream: BufReader::new(stream),
+            framing: ContentLength::default(),
+            pending: HashMap::new(),
+            notifications,
+            notification_replay: VecDeque::new(),
+            notification_replay_depth: 0,
+            replay_subscribers: Mutex::new(Vec::new()),
+            server_requests,
+            orphaned_responses,
+            closed,
+            handlers: HashMap::new(),
+            cancellable_handlers: HashMap::new(),
+            inbound_requests: HashMap::new(),
+            initialize_request: None,
+            open_documents: BTreeMap::new(),
+            next_id: 1,
+            shutting_down: false,
+            partial_results: HashMap::new(),
+            progress_tokens: HashSet::new(),
+            ended_progress: HashSet::new(),
+            progress_percentages: HashMap::new(),
+            child: None,
+            stderr: None,
+            pretty_logging: false,
+            message_log: None,
+            heartbeat: None,
+            initialize_options: InitializeOptions::default(),
+            content_modified_retries: 0,
+            position_encoding: PositionEncoding::default(),
+            text_document_sync: TextDocumentSyncKind::default(),
+            last_activity: Instant::now(),
+            #[cfg(feature = "metrics")]
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Adds a static header to every message written to the server.
+    /// See `ContentLength::with_header`.
+    pub fn with_header(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<Self> {
+        self.framing = self.framing.with_header(name, value)?;
+        Ok(self)
+    }
+
+    /// Fails reads of a message whose header block stalls for longer than `timeout`.
+    /// See `ContentLength::with_header_timeout`.
+    pub fn with_header_timeout(mut self, timeout: Duration) -> Self {
+        self.framing = self.framing.with_header_timeout(timeout);
+        self
+    }
+
+    /// Enables transparent gzip/deflate compression of server responses.
+    /// See `ContentLength::with_compression`.
+    #[cfg(feature = "compression")]
+    pub fn with_compression(mut self, enabled: bool) -> Self {
+        self.framing = self.framing.with_compression(enabled);
+        self
+    }
+}
+
+impl<F: Framing> LspClient<F> {
+    /// Replaces the framing used to delimit messages on the wire.
+    /// Use this to talk to servers that do not use the standard `Content-Length` headers.
+    pub fn with_framing<G: Framing>(self, framing: G) -> LspClient<G> {
+        LspClient {
+            stream: self.stream,
+            framing,
+            pending: self.pending,
+            notifications: self.notifications,
+            notification_replay: self.notification_replay,
+            notification_replay_depth: self.notification_replay_depth,
+            replay_subscribers: self.replay_subscribers,
+            server_requests: self.server_requests,
+            orphaned_responses: self.orphaned_responses,
+            closed: self.closed,
+            handlers: self.handlers,
+            cancellable_handlers: self.cancellable_handlers,
+            inbound_requests: self.inbound_requests,
+            initialize_request: self.initialize_request,
+            open_documents: self.open_documents,
+            next_id: self.next_id,
+            shutting_down: self.shutting_down,
+            partial_results: self.partial_results,
+            progress_tokens: self.progress_tokens,
+            ended_progress: self.ended_progress,
+            progress_percentages: self.progress_percentages,
+            child: self.child,
+            stderr: self.stderr,
+            pretty_logging: self.pretty_logging,
+            message_log: self.message_log,
+            heartbeat: self.heartbeat,
+            initialize_options: self.initialize_options,
+            content_modified_retries: self.content_modified_retries,
+            position_encoding: self.position_encoding,
+            text_document_sync: self.text_document_sync,
+            last_activity: self.last_activity,
+            #[cfg(feature = "metrics")]
+            metrics: self.metrics,
+        }
+    }
+
+    /// Sets the first id returned by `next_request_id`.
+    /// Together with a fixed `processId` in `initialize`, this makes the traffic written
+    /// by the client reproducible across runs, e.g. for snapshot tests.
+    pub fn with_id_start(mut self, start: u32) -> Self {
+        self.next_id = start;
+        self
+    }
+
+    /// Logs messages as indented JSON, which makes large payloads such as `initialize`
+    /// easier to read. Every message sent to or received from the server is logged at
+    /// trace level with the `log` crate, in compact form unless this is enabled.
+    /// Messages are always sent in compact form. Off by default.
+    pub fn with_pretty_logging(mut self, enabled: bool) -> Self {
+        self.pretty_logging = enabled;
+        self
+    }
+
+    /// Records every message sent to and received from the server, see `message_log`.
+    /// Bytes written with `write_raw` are not recorded.
+    pub fn with_message_log(mut self) -> Self {
+        self.message_log = Some(Vec::new());
+        self
+    }
+
+    /// Sets the timeout and retry count used by `initialize`.
+    pub fn with_initialize_options(mut self, options: InitializeOptions) -> Self {
+        self.initialize_options = options;
+        self
+    }
+
+    /// Makes `send_request_and_wait` resend a request, with a fresh id, when the server
+    /// answers it with a `ContentModified` error, up to `max_retries` times.
+    /// The last error is returned once the retries are exhausted. Off by default.
+    pub fn with_retry_on_content_modified(mut self, max_retries: u32) -> Self {
+        self.content_modified_retries = max_retries;
+        self
+    }
+
+    /// Enables the liveness check performed by `check_liveness`. Off by default.
+    pub fn with_heartbeat(mut self, heartbeat: Heartbeat) -> Self {
+        self.heartbeat = Some(heartbeat);
+        self
+    }
+
+    /// Keeps the last `depth` notifications and replays them to every subscriber created
+    /// afterwards with `notifications`, so subscribing late, e.g. after `initialize`, does
+    /// not miss the first `textDocument/publishDiagnostics`. Off by default.
+    pub fn with_notification_replay(mut self, depth: usize) -> Self {
+        self.notification_replay_depth = depth;
+        self
+    }
+
+    /// Subscribes to notifications sent by the server.
+    /// Notifications are delivered while `handle_response` reads from the stream,
+    /// and only to subscribers that exist at that point, unless a replay depth was set
+    /// with `with_notification_replay`.
+    /// Use `ServerNotification::from` to decode the ones the crate knows about.
+    pub fn notifications(&self) -> broadcast::Receiver<NotificationMessage> {
+        if self.notification_replay_depth == 0 {
+            return self.notifications.subscribe();
+        }
+        let (sender, receiver) =
+            broadcast::channel(NOTIFICATION_CAPACITY + self.notification_replay_depth);
+        for notification in &self.notification_replay {
+            let _ = sender.send(notification.clone());
+        }
+        self.replay_subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Returns a future that resolves once the connection to the server is closed,
+    /// i.e. a read hit EOF or a transport error, or the client shut the stream down.
+    /// The future does not borrow the client, so it can be awaited alongside a request.
+    pub fn closed(&self) -> impl std::future::Future<Output = ()> + Send + 'static {
+        let mut closed = self.closed.subscribe();
+        async move {
+            // An error means the client was dropped, which closes the connection too.
+            let _ = closed.wait_for(|closed| *closed).await;
+        }
+    }
+
+    /// Returns whether the connection to the server has been closed.
+    pub fn is_closed(&self) -> bool {
+        *self.closed.borrow()
+    }
+
+    /// Returns the id and method of every request still awaiting a response, oldest first.
+    /// Useful to find out which request a hanging server never answered.
+    pub fn pending_requests(&self) -> Vec<(RequestId, String)> {
+        let mut pending: Vec<(&RequestId, &PendingRequest)> = self.pending.iter().collect();
+        pending.sort_by_key(|(id, request)| (request.sent_at, *id));
+        pending
+            .into_iter()
+            .map(|(id, request)| (id.clone(), request.method.clone()))
+            .collect()
     }
 
+    /// Takes the stream of lines the spawned server writes to stderr.
+    /// Returns `None` if the client was not created with `spawn` or the stream was already taken.
+    pub fn server_stderr(&mut self) -> Option<UnboundedReceiverStream<String>> {
+        self.stderr.take().map(UnboundedReceiverStream::new)
+    }
+
+    /// Returns the exit status of the spawned server, or `None` if it is still running.
+    /// Fails if the client was not created with `spawn`.
+    pub fn server_exit_status(&mut self) -> Result<Option<ExitStatus>> {
+        let child = self
+            .child
+            .as_mut()
+            .ok_or_else(|| anyhow!("The LSP server was not spawned by this client"))?;
+        Ok(child.try_wait()?)
+    }
+
+    /// Subscribes to requests sent by the server, e.g. `workspace/semanticTokens/refresh`.
+    /// Every inbound request is delivered here, whether or not a handler answered it.
+    /// Use `ServerRequest::from` to decode the ones the crate knows about.
+    pub fn server_requests(&self) -> broadcast::Receiver<RequestMessage> {
+        self.server_requests.subscribe()
+    }
+
+    /// Subscribes to responses whose id matches no request in flight, e.g. a second answer
+    /// to the same request or an answer to a request that was never sent. These usually point
+    /// at an id bug in the client or server. Requests sent with `write_raw` are not tracked,
+    /// so their responses are reported here too. The response is still returned as usual.
+    pub fn orphaned_responses(&self) -> broadcast::Receiver<ResponseMessage> {
+        self.orphaned_responses.subscribe()
+    }
+
+    /// Returns a snapshot of the per-method request counts and latency percentiles.
+    #[cfg(feature = "metrics")]
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// Registers a handler for requests sent by the server with the given method,
+    /// e.g. `workspace/configuration` or `client/registerCapability`.
+    /// While reading responses, inbound requests are answered with the handler's result.
+    /// Requests without a registered handler are answered with a `MethodNotFound` error,
+    /// except for `window/showDocument`, which is answered with `{ "success": false }`.
+    ///
+    /// Handlers run to completion as soon as the request is read, before any later message,
+    /// so they cannot be cancelled; use `on_request_cancellable` for handlers that can.
+    pub fn on_request<H>(&mut self, method: impl Into<String>, handler: H)
+    where
+        H: Fn(serde_json::Value) -> Result<serde_json::Value, ResponseError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.handlers.insert(method.into(), Box::new(handler));
+    }
+
+    /// Registers an asynchronous handler for requests sent by the server with the given
+    /// method, which the server can cancel with `$/cancelRequest`. The handler's future is
+    /// not spawned, the client polls it whenever it reads from the server and writes its
+    /// answer as soon as it is ready. Its token fires when the cancellation is read; it
+    /// should then answer, usually with a `RequestCancelled` error.
+    /// Takes precedence over a handler registered with `on_request`.
+    pub fn on_request_cancellable<H, Fut>(&mut self, method: impl Into<String>, handler: H)
+    where
+        H: Fn(serde_json::Value, CancellationToken) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = HandlerResult> + Send + 'static,
+    {
+        self.cancellable_handlers.insert(
+            method.into(),
+            Box::new(move |params, token| Box::pin(handler(params, token))),
+        );
+    }
+
+    /// Sends a request, notification or response to the server.
+    ///
+    /// Messages are written in the order they are submitted: each call frames and flushes
+    /// its message before returning, and the client is borrowed mutably while doing so.
+    /// A `didChange` followed by a request on the same document is therefore guaranteed to
+    /// reach the server first, which servers rely on to answer against the new content.
     pub async fn send_request<T: Serialize + Debug>(&mut self, request: T) -> Result<()> {
-        println!("Sending request: {:?}", request);
+        if self.shutting_down {
+            let message = serde_json::to_value(&request)?;
+            if message.get("id").is_some() && message.get("method").is_some() {
+                return Err(ShuttingDown.into());
+            }
+        }
+        self.write_message(request).await
+    }
+
+    /// Returns the messages recorded so far, oldest first.
+    /// Empty unless enabled with `with_message_log`.
+    pub fn message_log(&self) -> &[LoggedMessage] {
+        self.message_log.as_deref().unwrap_or_default()
+    }
+
+    /// Writes the recorded messages to a file, one JSON object per line.
+    /// The file can be replayed with `MockServer::from_recording`.
+    pub fn save_message_log(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        let mut lines = String::new();
+        for entry in self.message_log() {
+            lines.push_str(&serde_json::to_string(entry)?);
+            lines.push('\n');
+        }
+        std::fs::write(path, lines)?;
+        Ok(())
+    }
+
+    /// Logs a message at trace level and adds it to the message log, if enabled.
+    fn record(&mut self, direction: Direction, message: &serde_json::Value) {
+        let verb = match direction {
+            Direction::Sent => "Sent",
+            Direction::Received => "Received",
+        };
+        if self.pretty_logging {
+            log::trace!("{} {:#}", verb, message);
+        } else {
+            log::trace!("{} {}", verb, message);
+        }
+        if let Some(log) = &mut self.message_log {
+            log.push(LoggedMessage {
+                direction,
+                message: message.clone(),
+            });
+        }
+    }
+
+    async fn write_message<T: Serialize + Debug>(&mut self, request: T) -> Result<()> {
         let request_str = serde_json::to_string(&request)?;
-        let content_length = request_str.len();
-        let header = format!("Content-Length: {}\r\n\r\n{}", content_length, request_str);
-        self.stream.write_all(header.as_bytes()).await?;
+        let framed = self.framing.encode(request_str.as_bytes())?;
+        self.stream.write_all(&framed).await?;
         self.stream.flush().await?;
+        let message = serde_json::to_value(&request)?;
+        #[cfg(feature = "metrics")]
+        if let Some(method) = message.get("method").and_then(|method| method.as_str()) {
+            self.metrics.record_bytes_sent(method, request_str.len());
+        }
+        self.record(Direction::Sent, &message);
+        self.track_request(&message);
+        self.track_document(&message);
+        self.cancel_stale_requests(&message).await
+    }
+
+    /// Cancels the in-flight requests about a document that was just changed, if the client
+    /// advertised `general.staleRequestSupport.cancel` in its `initialize` request.
+    /// The cancelled requests stay pending, since the server still answers them, usually
+    /// with a `RequestCancelled` or `ContentModified` error, but are only cancelled once.
+    /// Cancellations are sent in order of request id.
+    async fn cancel_stale_requests(&mut self, message: &serde_json::Value) -> Result<()> {
+        if message.get("method").and_then(|method| method.as_str())
+            != Some("textDocument/didChange")
+        {
+            return Ok(());
+        }
+        let cancel = self.initialize_request.as_ref().is_some_and(|request| {
+            request["params"]["capabilities"]["general"]["staleRequestSupport"]["cancel"]
+                == serde_json::Value::Bool(true)
+        });
+        let uri = message["params"]["textDocument"]["uri"].as_str();
+        if !cancel || uri.is_none() {
+            return Ok(());
+        }
+
+        let mut stale: Vec<RequestId> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| {
+                pending.uri.as_deref() == uri
+                    && pending.method != "initialize"
+                    && !pending.cancelled
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        stale.sort();
+
+        let mut buffer = Vec::new();
+        let mut cancellations = Vec::new();
+        for id in &stale {
+            let notification = NotificationMessage::new_cancel_request(id.clone());
+            let notification_str = serde_json::to_string(&notification)?;
+            buffer.extend(self.framing.encode(notification_str.as_bytes())?);
+            cancellations.push(serde_json::to_value(&notification)?);
+        }
+        if !buffer.is_empty() {
+            self.stream.write_all(&buffer).await?;
+            self.stream.flush().await?;
+        }
+        for id in &stale {
+            if let Some(pending) = self.pending.get_mut(id) {
+                pending.cancelled = true;
+            }
+        }
+        for cancellation in &cancellations {
+            self.record(Direction::Sent, cancellation);
+        }
         Ok(())
     }
 
-    pub async fn handle_response(&mut self) -> Result<ResponseMessage> {
-        loop {
-            let mut headers = Vec::new();
-            let mut content_length: Option<usize> = None;
+    /// Writes bytes directly to the stream, without framing or serialization.
+    ///
+    /// This is an escape hatch for protocol fuzzing, e.g. sending malformed headers to
+    /// see how a server reacts. It bypasses every guarantee the client otherwise provides:
+    /// nothing is validated, requests written this way are not tracked, and a partial
+    /// frame will corrupt the stream for any message sent after it.
+    pub async fn write_raw(&mut self, bytes: &[u8]) -> Result<()> {
+        self.stream.write_all(bytes).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
 
-            // Read headers
-            loop {
-                let mut byte = [0];
-                self.stream.read_exact(&mut byte).await?;
-                headers.push(byte[0]);
-
-                if headers.ends_with(b"\r\n\r\n") {
-                    let headers_str = String::from_utf8_lossy(&headers);
-                    for line in headers_str.lines() {
-                        if line.starts_with("Content-Length:") {
-                            let parts: Vec<&str> = line.splitn(2, ':').collect();
-                            if parts.len() > 1 {
-                                let length_str = parts[1].trim();
-                                content_length = Some(length_str.parse()?);
-                                break;
-                            }
-                        }
-                    }
-                    break; // Exit headers reading loop
-                }
+    /// Sends several notifications with a single write and flush.
+    /// Each notification is still framed individually, this is not a JSON-RPC batch.
+    pub async fn send_notifications(
+        &mut self,
+        notifications: Vec<NotificationMessage>,
+    ) -> Result<()> {
+        let mut buffer = Vec::new();
+        let mut messages = Vec::with_capacity(notifications.len());
+        for notification in notifications {
+            let notification_str = serde_json::to_string(&notification)?;
+            buffer.extend(self.framing.encode(notification_str.as_bytes())?);
+            #[cfg(feature = "metrics")]
+            self.metrics
+                .record_bytes_sent(&notification.method, notification_str.len());
+            messages.push(serde_json::to_value(&notification)?);
+        }
+
+        self.stream.write_all(&buffer).await?;
+        self.stream.flush().await?;
+        for message in &messages {
+            self.record(Direction::Sent, message);
+            self.track_document(message);
+            self.cancel_stale_requests(message).await?;
+        }
+        Ok(())
+    }
+
+    /// Sends a request for an arbitrary method with the given params,
+    /// which may be either a JSON object or an array of positional params.
+    pub async fn send_raw_request(
+        &mut self,
+        id: u32,
+        method: impl Into<String>,
+        params: serde_json::Value,
+    ) -> Result<()> {
+        self.send_request(RequestMessage::new_raw(id, method.into(), params))
+            .await
+    }
+
+    /// Returns the version the next change to an open document should carry, i.e. one more
+    /// than the version of the last `didOpen` or `didChange` sent for it.
+    /// Returns `None` if the document is not open.
+    pub fn next_version(&self, uri: &str) -> Option<i32> {
+        self.open_documents
+            .get(uri)
+            .map(|document| document.version + 1)
+    }
+
+    /// Sends a `textDocument/didChange` for an open document, numbered with `next_version`.
+    /// Returns the version that was sent.
+    pub async fn send_did_change(
+        &mut self,
+        uri: impl Into<String>,
+        content_changes: Vec<TextDocumentContentChangeEvent>,
+    ) -> Result<i32> {
+        let uri = uri.into();
+        let version = self
+            .next_version(&uri)
+            .ok_or_else(|| anyhow!("Cannot change {}, it is not open", uri))?;
+        self.send_request(NotificationMessage::new_did_change(
+            uri,
+            version,
+            content_changes,
+        ))
+        .await?;
+        Ok(version)
+    }
+
+    /// Remembers an outgoing request so its response can be correlated by id.
+    /// Notifications and responses we send have no `id` + `method` pair and are ignored.
+    fn track_request(&mut self, message: &serde_json::Value) {
+        let id = message.get("id").and_then(RequestId::from_value);
+        let method = message.get("method").and_then(|method| method.as_str());
+        if let (Some(id), Some(method)) = (id, method) {
+            if let RequestId::Number(number) = id {
+                let next = u32::try_from(number).unwrap_or(u32::MAX).saturating_add(1);
+                self.next_id = self.next_id.max(next);
+            }
+            if method == "initialize" {
+                self.initialize_request = Some(message.clone());
+            }
+            // Progress reported for a token the client passed in is tracked like progress
+            // for a token the server created.
+            if let Some(Ok(token)) = message["params"]
+                .get("workDoneToken")
+                .map(|token| serde_json::from_value::<ProgressToken>(token.clone()))
+            {
+                self.ended_progress.remove(&token);
+                self.progress_percentages.remove(&token);
+                self.progress_tokens.insert(token);
             }
+            #[cfg(feature = "metrics")]
+            self.metrics.record_request(method);
+            self.pending.insert(
+                id,
+                PendingRequest {
+                    method: method.to_string(),
+                    sent_at: Instant::now(),
+                    uri: message["params"]["textDocument"]["uri"]
+                        .as_str()
+                        .map(str::to_string),
+                    cancelled: false,
+                },
+            );
+        }
+    }
 
-            let content_length =
-                content_length.ok_or_else(|| anyhow!("Failed to find Content-Length header"))?;
-            let mut body = vec![0u8; content_length];
-            self.stream.read_exact(&mut body).await?;
-            println!("Response body: {:?}", String::from_utf8_lossy(&body));
-            let response: ResponseMessage = serde_json::from_slice(&body)
-                .map_err(|e| anyhow!("Failed to parse response body: {}", e))?;
+    /// Keeps track of the documents opened on the server so they can be replayed
+    /// by `reinitialize`. Only full-text changes update the tracked content.
+    fn track_document(&mut self, message: &serde_json::Value) {
+        let method = message.get("method").and_then(|method| method.as_str());
+        let text_document = &message["params"]["textDocument"];
+        let Some(uri) = text_document["uri"].as_str() else {
+            return;
+        };
 
-            // If response has a valid id, return it
-            if response.id.is_some() {
-                return Ok(response);
+        match method {
+            Some("textDocument/didOpen") => {
+                if let Ok(item) = serde_json::from_value(text_document.clone()) {
+                    self.open_documents.insert(uri.to_string(), item);
+                }
+            }
+            Some("textDocument/didChange") => {
+                if let Some(item) = self.open_documents.get_mut(uri) {
+                    if let Some(version) = text_document["version"].as_i64() {
+                        item.version = version as i32;
+                    }
+                    let changes = message["params"]["contentChanges"].as_array();
+                    let full_text = changes
+                        .and_then(|changes| changes.last())
+                        .filter(|change| change.get("range").is_none())
+                        .and_then(|change| change["text"].as_str());
+                    if let Some(text) = full_text {
+                        item.text = text.to_string();
+                    }
+                }
             }
+            Some("textDocument/didClose") => {
+                self.open_documents.remove(uri);
+            }
+            _ => {}
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::protocol::RequestMessage;
-    use serde_json::json;
-    use tokio_test::io::Builder;
+    /// Replaces the underlying stream, e.g. after reconnecting to a restarted server.
+    /// Requests that were still awaiting a response on the old stream are forgotten, as are
+    /// the server's requests that were still being answered, and requests are accepted again
+    /// after a `graceful_shutdown`.
+    pub fn replace_stream(&mut self, stream: impl AsyncReadWrite + Send + 'static) {
+        self.stream = BufReader::new(Box::pin(stream));
+        self.pending.clear();
+        self.inbound_requests.clear();
+        self.shutting_down = false;
+        self.closed.send_replace(false);
+    }
 
-    #[tokio::test]
-    async fn test_send_request_and_response() {
-        // Assume this is the exact request JSON your client will send
-        let request = RequestMessage::new_initialize(
-            1,
-            std::process::id(),
-            "file:///tmp".into(),
-            "unit_test_client".into(),
-            "0.1.0".into(),
-            vec![],
-        );
+    /// Performs the initialize handshake: sends the `initialize` request, waits for its
+    /// response and then sends the `initialized` notification.
+    /// Unlike other requests, each attempt waits up to `InitializeOptions::timeout` and is
+    /// retried with a fresh id on timeout or error, since servers may still be starting up.
+    /// Notifications and requests the server sends before answering are dispatched as usual;
+    /// only a response with the id of the current attempt completes the handshake.
+    pub async fn initialize(&mut self, mut request: RequestMessage) -> Result<InitializeResult> {
+        let options = self.initialize_options.clone();
+        let mut attempt = 0;
+        let result = loop {
+            if attempt > 0 {
+                request.id = serde_json::Value::from(self.next_request_id());
+            }
+            let id = RequestId::from_value(&request.id)
+                .ok_or_else(|| anyhow!("Invalid request id {}", request.id))?;
+            self.send_request(request.clone()).await?;
 
-        let request_json = serde_json::to_string(&request).unwrap();
-        let request_content_length = request_json.len();
+            let outcome =
+                match tokio::time::timeout(options.timeout, self.wait_for_response(&id)).await {
+                    Ok(response) => response?.handle_initialize(),
+                    Err(_) => Err(anyhow!(
+                        "Timed out after {:?} waiting for the initialize response",
+                        options.timeout
+                    )),
+                };
+            match outcome {
+                Ok(result) => break result,
+                Err(err) if attempt >= options.retries => {
+                    return Err(err.context(format!(
+                        "Initialize failed after {} attempt(s)",
+                        attempt + 1
+                    )))
+                }
+                Err(_) => {}
+            }
+            attempt += 1;
+        };
 
-        // The actual response from the server
-        let response_payload = json!({
-            "jsonrpc": "2.0",
-            "id": 1, // Match the ID of the request
-            "result": {}
-        })
-        .to_string();
-        let response_content_length = response_payload.len();
-        let server_response = format!(
-            "Content-Length: {}\r\n\r\n{}",
-            response_content_length, response_payload
-        );
+        self.position_encoding = result.capabilities.position_encoding.unwrap_or_default();
+        self.text_document_sync = result.capabilities.text_document_sync_kind();
+        self.send_request(NotificationMessage::new_initialized())
+            .await?;
+        Ok(result)
+    }
 
-        // Set up the mock server
-        let mock_server = Builder::new()
-            .write(
-                format!(
-                    "Content-Length: {}\r\n\r\n{}",
-                    request_content_length, request_json
+    /// Returns the position encoding the server chose in its `initialize` result,
+    /// or UTF-16 if it did not choose one or `initialize` has not completed.
+    pub fn position_encoding(&self) -> PositionEncoding {
+        self.position_encoding
+    }
+
+    /// Returns how the server wants `textDocument/didChange` to be sent, as advertised in
+    /// its `initialize` result, i.e. whether to send the full text or incremental edits.
+    /// `None` if the server did not advertise it or `initialize` has not completed.
+    pub fn text_document_sync(&self) -> TextDocumentSyncKind {
+        self.text_document_sync
+    }
+
+    /// Returns the hover `contentFormat` the client advertised in its `initialize` request,
+    /// in order of preference, or nothing if `initialize` has not been sent.
+    pub fn hover_content_format(&self) -> Vec<String> {
+        self.initialize_request
+            .as_ref()
+            .and_then(|request| {
+                serde_json::from_value(
+                    request["params"]["capabilities"]["textDocument"]["hover"]["contentFormat"]
+                        .clone(),
                 )
-                .as_bytes(),
-            )
-            .read(server_response.as_bytes())
-            .build();
+                .ok()
+            })
+            .unwrap_or_default()
+    }
 
-        let mut lsp_client = LspClient {
-            stream: Box::pin(mock_server),
-        };
+    /// Re-runs the initialize handshake with the last `initialize` request that was sent
+    /// and replays `textDocument/didOpen` for every document that is still open.
+    /// This is meant to be used after `replace_stream` when a server has been restarted.
+    /// The request is resent with a fresh id and goes through `initialize`, so late
+    /// responses from the previous session cannot be mistaken for its result.
+    pub async fn reinitialize(&mut self) -> Result<InitializeResult> {
+        let mut initialize: RequestMessage =
+            serde_json::from_value(self.initialize_request.clone().ok_or_else(|| {
+                anyhow!("Cannot reinitialize before an initialize request was sent")
+            })?)?;
+        initialize.id = serde_json::Value::from(self.next_request_id());
+        let result = self.initialize(initialize).await?;
 
-        // Test sending the request
-        let send_result = lsp_client.send_request(request).await;
-        assert!(send_result.is_ok());
+        let documents: Vec<TextDocumentItem> = self.open_documents.values().cloned().collect();
+        for document in documents {
+            self.send_request(NotificationMessage::new_did_open(
+                document.uri,
+                document.language_id,
+                document.version,
+                document.text,
+            ))
+            .await?;
+        }
 
-        // Test handling the response
-        let response = lsp_client.handle_response().await;
-        assert!(response.is_ok());
-        assert_eq!(response.unwrap().result.unwrap(), json!({}));
+        Ok(result)
+    }
+
+    /// Returns an id that has not been used by any request sent so far.
+    /// Ids are handed out sequentially, starting at 1 unless set with `with_id_start`.
+    pub fn next_request_id(&mut self) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    /// Reads messages until the response to the request with the given id arrives.
+    /// Responses to other requests received in the meantime are discarded.
+    async fn wait_for_response(&mut self, id: &RequestId) -> Result<ResponseMessage> {
+        loop {
+            let response = self.handle_response().await?;
+            if response
+                .id
+                .as_ref()
+                .and_then(RequestId::from_value)
+                .as_ref()
+                == Some(id)
+            {
+                return Ok(response);
+            }
+        }
+    }
+
+    /// Probes the server if nothing has been read from it for the heartbeat interval.
+    /// LSP has no ping, so a request for the heartbeat method is sent and the server must
+    /// answer it, successfully or not, within the heartbeat timeout. Otherwise the connection
+    /// is flagged as closed, see `is_closed`, and an error is returned.
+    /// Does nothing unless enabled with `with_heartbeat`. Call it periodically while idle,
+    /// e.g. from a `tokio::time::interval` loop.
+    pub async fn check_liveness(&mut self) -> Result<()> {
+        let Some(heartbeat) = self.heartbeat.clone() else {
+            return Ok(());
+        };
+        if self.last_activity.elapsed() < heartbeat.interval {
+            return Ok(());
+        }
+
+        let id = self.next_request_id();
+        self.send_request(RequestMessage::new_raw(
+            id,
+            heartbeat.method,
+            serde_json::Value::Null,
+        ))
+        .await?;
+        let probe_id = RequestId::Number(id.into());
+        match tokio::time::timeout(heartbeat.timeout, self.wait_for_response(&probe_id)).await {
+            Ok(response) => response.map(|_| ()),
+            Err(_) => {
+                self.closed.send_replace(true);
+                bail!(
+                    "The LSP server did not answer a heartbeat within {:?}",
+                    heartbeat.timeout
+                )
+            }
+        }
+    }
+
+    /// Shuts the server down without losing responses to requests that are still in flight.
+    /// New requests are rejected with `ShuttingDown` while outstanding ones are awaited
+    /// until `timeout` elapses. Then `shutdown` and `exit` are sent and the stream is closed.
+    /// The server gets another `timeout` to acknowledge `shutdown`, so that requests which
+    /// never complete do not leave it without one; the call can take up to twice `timeout`.
+    /// Returns the outcome of every request that was in flight when this was called;
+    /// those still unanswered at the deadline resolve with a `ShuttingDown` error.
+    pub async fn graceful_shutdown(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Vec<(RequestId, Result<ResponseMessage>)>> {
+        self.shutting_down = true;
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut in_flight: BTreeMap<RequestId, Result<ResponseMessage>> = self
+            .pending
+            .keys()
+            .map(|id| (id.clone(), Err(ShuttingDown.into())))
+            .collect();
+
+        while in_flight.keys().any(|id| self.pending.contains_key(id)) {
+            let response = match tokio::time::timeout_at(deadline, self.handle_response()).await {
+                Ok(response) => response?,
+                Err(_) => break,
+            };
+            if let Some(id) = response.id.as_ref().and_then(RequestId::from_value) {
+                if let Some(outcome) = in_flight.get_mut(&id) {
+                    *outcome = Ok(response);
+                }
+            }
+        }
+
+        // The budget for in-flight requests may be spent, the shutdown request gets its own.
+        tokio::time::timeout(timeout, self.request_shutdown())
+            .await
+            .map_err(|_| anyhow!("Timed out waiting for the shutdown response"))??;
+        self.send_exit().await?;
+
+        Ok(in_flight.into_iter().collect())
+    }
+
+    /// Sends `shutdown` and waits for the server to acknowledge it, without sending `exit`.
+    /// The connection stays open, so the server's behavior between the two can be tested;
+    /// call `send_exit` afterwards to finish the shutdown.
+    pub async fn request_shutdown(&mut self) -> Result<()> {
+        let id = self.next_request_id();
+        self.write_message(RequestMessage::new_shutdown(id)).await?;
+        self.wait_for_response(&RequestId::Number(id.into()))
+            .await?
+            .handle_shutdown()
+    }
+
+    /// Sends the `exit` notification and closes the stream to the server.
+    pub async fn send_exit(&mut self) -> Result<()> {
+        self.write_message(NotificationMessage::new_exit()).await?;
+        self.stream.shutdown().await?;
+        self.closed.send_replace(true);
+        Ok(())
+    }
+
+    /// Removes the pending entry matching a received response.
+    fn complete_request(&mut self, response: &ResponseMessage) {
+        let Some(id) = response.id.as_ref().and_then(RequestId::from_value) else {
+            return;
+        };
+        let Some(pending) = self.pending.remove(&id) else {
+            // Nobody listening is fine, the response is returned regardless.
+            let _ = self.orphaned_responses.send(response.clone());
+            return;
+        };
+        #[cfg(feature = "metrics")]
+        self.metrics
+            .record_response(&pending.method, pending.sent_at.elapsed());
+        #[cfg(not(feature = "metrics"))]
+        drop(pending);
+    }
+
+    pub async fn handle_response(&mut self) -> Result<ResponseMessage> {
+        loop {
+            if let Some(response) = self.read_message().await? {
+                return Ok(response);
+            }
+        }
+    }
+
+    /// Reads and dispatches a single message, returning it if it is a response.
+    async fn read_message(&mut self) -> Result<Option<ResponseMessage>> {
+        // Cancellable handlers are polled, and their answers written, while waiting for the
+        // next message. `fill_buf` does not consume anything, so it can be abandoned for one.
+        while !self.inbound_requests.is_empty() {
+            tokio::select! {
+                biased;
+                (id, answer) = poll_fn(|cx| poll_inbound(&mut self.inbound_requests, cx)) => {
+                    self.send_answer(id, answer).await?
+                }
+                _ = self.stream.fill_buf() => break,
+            }
+        }
+        let body = match self.framing.decode(&mut self.stream).await {
+            Ok(body) => body,
+            Err(err) => {
+                if err.downcast_ref::<std::io::Error>().is_some() {
+                    self.closed.send_replace(true);
+                }
+                return Err(err);
+            }
+        };
+        self.last_activity = Instant::now();
+        let message: serde_json::Value = serde_json::from_slice(&body)
+            .map_err(|e| anyhow!("Failed to parse response body: {}", e))?;
+        self.record(Direction::Received, &message);
+        #[cfg(feature = "metrics")]
+        {
+            let method = match message.get("method").and_then(|method| method.as_str()) {
+                Some(method) => Some(method.to_string()),
+                None => message
+                    .get("id")
+                    .and_then(RequestId::from_value)
+                    .and_then(|id| self.pending.get(&id))
+                    .map(|pending| pending.method.clone()),
+            };
+            if let Some(method) = method {
+                self.metrics.record_bytes_received(&method, body.len());
+            }
+        }
+
+        if message.get("method").is_some() && message.get("id").is_none() {
+            let notification: NotificationMessage = serde_json::from_value(message)
+                .map_err(|e| anyhow!("Failed to parse notification: {}", e))?;
+            self.dispatch_notification(notification);
+            return Ok(None);
+        }
+
+        if message.get("method").is_some() {
+            let request: RequestMessage = serde_json::from_value(message)
+                .map_err(|e| anyhow!("Failed to parse server request: {}", e))?;
+            self.answer_server_request(request).await?;
+            return Ok(None);
+        }
+
+        let response: ResponseMessage = serde_json::from_value(message)
+            .map_err(|e| anyhow!("Failed to parse response body: {}", e))?;
+
+        // If response has a valid id, return it
+        if response.id.is_some() {
+            self.complete_request(&response);
+            return Ok(Some(response));
+        }
+        Ok(None)
+    }
+
+    /// Collects partial results for the tokens being awaited and forwards the
+    /// notification to subscribers.
+    fn dispatch_notification(&mut self, notification: NotificationMessage) {
+        if notification.method == "$/progress" {
+            if let Ok(progress) =
+                serde_json::from_value::<ProgressParams>(notification.params.clone())
+            {
+                // Progress for a token that is not tracked is only forwarded to subscribers.
+                if let Some(chunks) = self.partial_results.get_mut(&progress.token) {
+                    chunks.push(progress.value);
+                } else if self.progress_tokens.contains(&progress.token) {
+                    match progress.work_done() {
+                        Some(WorkDoneProgress::End { .. }) => {
+                            self.progress_tokens.remove(&progress.token);
+                            self.ended_progress.insert(progress.token);
+                        }
+                        Some(work_done) => {
+                            if let Some(percentage) = work_done.percentage() {
+                                self.progress_percentages.insert(progress.token, percentage);
+                            }
+                        }
+                        None => {}
+                    }
+                }
+            }
+        }
+
+        if notification.method == "$/cancelRequest" {
+            if let Some(cancel) = RequestId::from_value(&notification.params["id"])
+                .and_then(|id| self.inbound_requests.get(&id))
+            {
+                cancel.cancel.send_replace(true);
+            }
+        }
+
+        if self.notification_replay_depth > 0 {
+            if self.notification_replay.len() == self.notification_replay_depth {
+                self.notification_replay.pop_front();
+            }
+            self.notification_replay.push_back(notification.clone());
+            // Subscribers that were dropped are forgotten.
+            self.replay_subscribers
+                .get_mut()
+                .unwrap()
+                .retain(|sender| sender.send(notification.clone()).is_ok());
+        }
+
+        // Nobody listening is fine, the notification is simply dropped.
+        let _ = self.notifications.send(notification);
+    }
+
+    /// Returns whether this work done progress token is tracked, i.e. the server created it
+    /// with `window/workDoneProgress/create` or the client sent it as the `workDoneToken`
+    /// of a request, and the server has not ended its progress yet.
+    pub fn has_progress_token(&self, token: &ProgressToken) -> bool {
+        self.progress_tokens.contains(token)
+    }
+
+    /// Reads messages until the server ends the work done progress with the given token,
+    /// e.g. to wait for the initial indexing the server reports after `initialize`.
+    /// Returns immediately if that progress already ended. Only tokens the server created
+    /// with `window/workDoneProgress/create`, or passed as a request's `workDoneToken`,
+    /// are tracked. Responses received in the meantime are discarded.
+    pub async fn await_progress_end(
+        &mut self,
+        token: &ProgressToken,
+        timeout: Duration,
+    ) -> Result<()> {
+        let wait = async {
+            while !self.ended_progress.contains(token) {
+                self.read_message().await?;
+            }
+            Ok(())
+        };
+        tokio::time::timeout(timeout, wait).await.map_err(|_| {
+            anyhow!(
+                "Timed out after {:?} waiting for progress {:?} to end",
+                timeout,
+                token
+            )
+        })?
+    }
+
+    /// Returns the last percentage the server reported for the work done progress with the
+    /// given token, if it reported any.
+    pub fn progress_percentage(&self, token: &ProgressToken) -> Option<u32> {
+        self.progress_percentages.get(token).copied()
+    }
+
+    /// Reads messages until the server reports at least `percentage` for the work done
+    /// progress with the given token, or ends it. Useful to proceed once indexing is
+    /// mostly done. Responses received in the meantime are discarded.
+    pub async fn await_progress_percentage(
+        &mut self,
+        token: &ProgressToken,
+        percentage: u32,
+        timeout: Duration,
+    ) -> Result<()> {
+        let wait = async {
+            while !self.ended_progress.contains(token)
+                && self.progress_percentage(token).unwrap_or(0) < percentage
+            {
+                self.read_message().await?;
+            }
+            Ok(())
+        };
+        tokio::time::timeout(timeout, wait).await.map_err(|_| {
+            anyhow!(
+                "Timed out after {:?} waiting for progress {:?} to reach {}%",
+                timeout,
+                token,
+                percentage
+            )
+        })?
+    }
+
+    /// Sends a request with a partial result token and collects the chunks the server
+    /// streams via `$/progress` until the final response arrives.
+    pub async fn request_with_partial_results(
+        &mut self,
+        request: RequestMessage,
+        token: ProgressToken,
+    ) -> Result<PartialResultResponse> {
+        let id = RequestId::from_value(&request.id)
+            .ok_or_else(|| anyhow!("Invalid request id {}", request.id))?;
+        self.partial_results.insert(token.clone(), Vec::new());
+        let response = async {
+            self.send_request(request.with_partial_result_token(token.clone()))
+                .await?;
+            self.wait_for_response(&id).await
+        }
+        .await;
+        let partial_results = self.partial_results.remove(&token).unwrap_or_default();
+
+        Ok(PartialResultResponse {
+            partial_results,
+            response: response?,
+        })
+    }
+
+    /// Reads the next response and checks that it answers the request with the given id.
+    /// Fails with `ResponseIdMismatch` otherwise, e.g. when a stale response to a
+    /// cancelled request arrives first.
+    pub async fn handle_response_for(&mut self, expected: &RequestId) -> Result<ResponseMessage> {
+        let response = self.handle_response().await?;
+        if response
+            .id
+            .as_ref()
+            .and_then(RequestId::from_value)
+            .as_ref()
+            != Some(expected)
+        {
+            return Err(ResponseIdMismatch {
+                expected: expected.clone(),
+                actual: response.id,
+            }
+            .into());
+        }
+        Ok(response)
+    }
+
+    /// Sends a request without waiting for its response. Requests sent this way are in
+    /// flight on the server at the same time; use `join_responses` to read their responses.
+    pub async fn send_request_async(&mut self, request: RequestMessage) -> Result<ResponseHandle> {
+        let id = RequestId::from_value(&request.id)
+            .ok_or_else(|| anyhow!("Invalid request id {}", request.id))?;
+        self.send_request(request).await?;
+        Ok(ResponseHandle { id })
+    }
+
+    /// Reads messages until every request of `handles` is answered, in whatever order the
+    /// server answers them, and returns the responses in the order of `handles`.
+    /// Responses to other requests received in the meantime are discarded.
+    pub async fn join_responses(
+        &mut self,
+        handles: &[ResponseHandle],
+    ) -> Result<Vec<ResponseMessage>> {
+        let mut responses: HashMap<RequestId, ResponseMessage> = HashMap::new();
+        while handles
+            .iter()
+            .any(|handle| !responses.contains_key(&handle.id))
+        {
+            let response = self.handle_response().await?;
+            let id = response.id.as_ref().and_then(RequestId::from_value);
+            if let Some(id) = id.filter(|id| handles.iter().any(|handle| &handle.id == id)) {
+                responses.insert(id, response);
+            }
+        }
+        Ok(handles
+            .iter()
+            .map(|handle| responses[&handle.id].clone())
+            .collect())
+    }
+
+    /// Sends a request and reads its response, verifying that the response ids match.
+    /// A `ContentModified` error is retried if enabled with `with_retry_on_content_modified`.
+    pub async fn send_request_and_wait(
+        &mut self,
+        mut request: RequestMessage,
+    ) -> Result<ResponseMessage> {
+        let mut attempt = 0;
+        loop {
+            let id = RequestId::from_value(&request.id)
+                .ok_or_else(|| anyhow!("Invalid request id {}", request.id))?;
+            self.send_request(request.clone()).await?;
+            let response = self.handle_response_for(&id).await?;
+
+            let content_modified = response
+                .error
+                .as_ref()
+                .is_some_and(|error| error.code == LspErrorCode::ContentModified);
+            if !content_modified || attempt >= self.content_modified_retries {
+                return Ok(response);
+            }
+            attempt += 1;
+            request.id = serde_json::Value::from(self.next_request_id());
+        }
+    }
+
+    /// Requests completions at a position once for every trigger character the server
+    /// advertises in `completionProvider.triggerCharacters`, each with a `TriggerCharacter`
+    /// context. Returns the completions keyed by trigger character; the map is empty if the
+    /// server advertises none. Fails on the first request the server answers with an error.
+    pub async fn complete_trigger_characters(
+        &mut self,
+        capabilities: &ServerCapabilities,
+        uri: impl Into<String>,
+        position: Position,
+    ) -> Result<BTreeMap<String, CompletionList>> {
+        let uri = uri.into();
+        let trigger_characters = capabilities.completion_trigger_characters().to_vec();
+
+        let mut completions = BTreeMap::new();
+        for trigger_character in trigger_characters {
+            let request = RequestMessage::new_completion(
+                self.next_request_id(),
+                uri.clone(),
+                position,
+                Some(CompletionContext {
+                    trigger_kind: CompletionTriggerKind::TriggerCharacter,
+                    trigger_character: Some(trigger_character.clone()),
+                }),
+            );
+            let list = self
+                .send_request_and_wait(request)
+                .await?
+                .handle_completion()?;
+            completions.insert(trigger_character, list);
+        }
+        Ok(completions)
+    }
+
+    /// Dispatches a request sent by the server to its registered handler and writes the reply.
+    async fn answer_server_request(&mut self, request: RequestMessage) -> Result<()> {
+        // Nobody listening is fine, the request is answered regardless.
+        let _ = self.server_requests.send(request.clone());
+
+        if request.method == "window/workDoneProgress/create" {
+            if let Ok(params) =
+                serde_json::from_value::<WorkDoneProgressCreateParams>(request.params.clone())
+            {
+                self.ended_progress.remove(&params.token);
+                self.progress_percentages.remove(&params.token);
+                self.progress_tokens.insert(params.token);
+            }
+        }
+
+        if let Some(handler) = self.cancellable_handlers.get(&request.method) {
+            if let Some(id) = RequestId::from_value(&request.id) {
+                let (cancel, token) = watch::channel(false);
+                let answer = handler(request.params, CancellationToken(token));
+                self.inbound_requests.insert(
+                    id,
+                    InboundRequest {
+                        id: request.id,
+                        cancel,
+                        answer,
+                    },
+                );
+                return Ok(());
+            }
+        }
+
+        let result = match self.handlers.get(&request.method) {
+            Some(handler) => handler(request.params),
+            None if AUTO_ACKNOWLEDGED_REQUESTS.contains(&request.method.as_str()) => {
+                Ok(serde_json::Value::Null)
+            }
+            // Nothing is shown without a handler, which the result reports to the server.
+            None if request.method == "window/showDocument" => {
+                Ok(serde_json::to_value(ShowDocumentResult { success: false })?)
+            }
+            None => Err(ResponseError::new(
+                LspErrorCode::MethodNotFound,
+                format!("Unhandled method {}", request.method),
+            )),
+        };
+
+        self.send_answer(request.id, result).await
+    }
+
+    async fn send_answer(&mut self, id: serde_json::Value, result: HandlerResult) -> Result<()> {
+        if let Some(request_id) = RequestId::from_value(&id) {
+            self.inbound_requests.remove(&request_id);
+        }
+        let response = match result {
+            Ok(result) => ResponseMessage::new_result(id, result),
+            Err(error) => ResponseMessage::new_error(id, error),
+        };
+        self.send_request(response).await
+    }
+
+    /// Sends a request and returns the error the server answered it with.
+    /// Fails if the server returned a successful result instead.
+    /// This is intended for negative tests that check a server rejects bad input.
+    /// Responses to other requests received in the meantime are discarded.
+    pub async fn expect_error<T: Serialize + Debug>(
+        &mut self,
+        request: T,
+    ) -> Result<ResponseError> {
+        let message = serde_json::to_value(&request)?;
+        let id = message
+            .get("id")
+            .and_then(RequestId::from_value)
+            .ok_or_else(|| anyhow!("Cannot expect an error for a message without an id"))?;
+        self.send_request(request).await?;
+        let response = self.wait_for_response(&id).await?;
+        response.error.ok_or_else(|| {
+            anyhow!(
+                "Expected an error from LSP server, got result: {:?}",
+                response.result
+            )
+        })
+    }
+
+    /// Fails with a list of everything a test did not account for: requests that were never
+    /// answered, messages the server sent that were not read yet, and messages subscribers of
+    /// `notifications`, `server_requests` or `orphaned_responses` have not received.
+    /// Only messages that already arrived are checked; they are consumed without being
+    /// dispatched, so that they can be listed.
+    pub async fn assert_drained(&mut self) -> Result<()> {
+        let mut leftovers: Vec<String> = self
+            .pending_requests()
+            .into_iter()
+            .map(|(id, method)| format!("request {} ({}) was never answered", id, method))
+            .collect();
+
+        // A zero timeout still polls once, which picks up anything already received.
+        while let Ok(Ok(buffer)) =
+            tokio::time::timeout(Duration::ZERO, self.stream.fill_buf()).await
+        {
+            if buffer.is_empty() {
+                break;
+            }
+            let body = self.framing.decode(&mut self.stream).await?;
+            let message: serde_json::Value = serde_json::from_slice(&body)
+                .map_err(|e| anyhow!("Failed to parse response body: {}", e))?;
+            self.record(Direction::Received, &message);
+            leftovers.push(match message["method"].as_str() {
+                Some(method) => format!("{} from the server was not read", method),
+                None => format!("response to request {} was not read", message["id"]),
+            });
+        }
+
+        let replayed: usize = self
+            .replay_subscribers
+            .get_mut()
+            .unwrap()
+            .iter()
+            .map(|sender| sender.len())
+            .sum();
+        for (count, kind) in [
+            (self.notifications.len() + replayed, "notifications"),
+            (self.server_requests.len(), "server requests"),
+            (self.orphaned_responses.len(), "orphaned responses"),
+        ] {
+            if count > 0 {
+                leftovers.push(format!(
+                    "{} {} were not received by a subscriber",
+                    count, kind
+                ));
+            }
+        }
+
+        if !leftovers.is_empty() {
+            bail!("The client is not drained:\n{}", leftovers.join("\n"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{
+        ClientCapabilities, MarkupKind, ServerNotification, ServerRequest, TelemetryEventParams,
+    };
+    use serde_json::json;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use tokio_test::io::Builder;
+
+    #[tokio::test]
+    async fn test_send_request_and_response() {
+        // Assume this is the exact request JSON your client will send
+        let request = RequestMessage::new_initialize(
+            1,
+            Some(std::process::id()),
+            "file:///tmp".into(),
+            "unit_test_client".into(),
+            Some("0.1.0".into()),
+            vec![],
+        );
+
+        let request_json = serde_json::to_string(&request).unwrap();
+        let request_content_length = request_json.len();
+
+        // The actual response from the server
+        let response_payload = json!({
+            "jsonrpc": "2.0",
+            "id": 1, // Match the ID of the request
+            "result": {}
+        })
+        .to_string();
+        let response_content_length = response_payload.len();
+        let server_response = format!(
+            "Content-Length: {}\r\n\r\n{}",
+            response_content_length, response_payload
+        );
+
+        // Set up the mock server
+        let mock_server = Builder::new()
+            .write(
+                format!(
+                    "Content-Length: {}\r\n\r\n{}",
+                    request_content_length, request_json
+                )
+                .as_bytes(),
+            )
+            .read(server_response.as_bytes())
+            .build();
+
+        let mut lsp_client = LspClient::from_stream(mock_server);
+
+        // Test sending the request
+        let send_result = lsp_client.send_request(request).await;
+        assert!(send_result.is_ok());
+
+        // Test handling the response
+        let response = lsp_client.handle_response().await;
+        assert!(response.is_ok());
+        assert_eq!(response.unwrap().result.unwrap(), json!({}));
+    }
+
+    /// A newline-delimited JSON framing used to exercise custom framers.
+    struct JsonLines;
+
+    impl Framing for JsonLines {
+        fn encode(&self, body: &[u8]) -> Result<Vec<u8>> {
+            let mut framed = body.to_vec();
+            framed.push(b'\n');
+            Ok(framed)
+        }
+
+        async fn decode<R: AsyncRead + Unpin + Send>(&self, reader: &mut R) -> Result<Vec<u8>> {
+            use tokio::io::AsyncReadExt;
+
+            let mut body = Vec::new();
+            loop {
+                let mut byte = [0];
+                reader.read_exact(&mut byte).await?;
+                if byte[0] == b'\n' {
+                    return Ok(body);
+                }
+                body.push(byte[0]);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_framing() {
+        let request = RequestMessage::new_get_definition(
+            7,
+            "file:///tmp/main.go".into(),
+            Position::new(1, 2),
+        );
+        let request_json = serde_json::to_string(&request).unwrap();
+
+        let response_payload = json!({
+            "jsonrpc": "2.0",
+            "id": 7,
+            "result": null
+        })
+        .to_string();
+
+        let mock_server = Builder::new()
+            .write(format!("{}\n", request_json).as_bytes())
+            .read(format!("{}\n", response_payload).as_bytes())
+            .build();
+
+        let mut lsp_client = LspClient::from_stream(mock_server).with_framing(JsonLines);
+
+        lsp_client.send_request(request).await.unwrap();
+        let response = lsp_client.handle_response().await.unwrap();
+        assert_eq!(response.id, Some(json!(7)));
+    }
+
+    #[tokio::test]
+    async fn test_expect_error() {
+        let request = RequestMessage::new_get_definition(
+            3,
+            "file:///tmp/main.go".into(),
+            Position::new(0, 0),
+        );
+        let request_json = serde_json::to_string(&request).unwrap();
+
+        let response_payload = json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "error": { "code": -32602, "message": "bad position" }
+        })
+        .to_string();
+
+        let mock_server = Builder::new()
+            .write(
+                format!(
+                    "Content-Length: {}\r\n\r\n{}",
+                    request_json.len(),
+                    request_json
+                )
+                .as_bytes(),
+            )
+            .read(
+                format!(
+                    "Content-Length: {}\r\n\r\n{}",
+                    response_payload.len(),
+                    response_payload
+                )
+                .as_bytes(),
+            )
+            .build();
+
+        let mut lsp_client = LspClient::from_stream(mock_server);
+
+        let error = lsp_client.expect_error(request).await.unwrap();
+        assert_eq!(error.code, crate::protocol::LspErrorCode::InvalidParams);
+    }
+
+    #[tokio::test]
+    async fn test_expect_error_skips_unrelated_responses() {
+        let request = RequestMessage::new_get_definition(
+            3,
+            "file:///tmp/main.go".into(),
+            Position::new(0, 0),
+        );
+        let frame = |body: &str| format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let unrelated = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "error": { "code": -32601, "message": "unknown method" }
+        })
+        .to_string();
+        let response = json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "error": { "code": -32602, "message": "bad position" }
+        })
+        .to_string();
+        let mock_server = Builder::new()
+            .write(frame(&serde_json::to_string(&request).unwrap()).as_bytes())
+            .read(frame(&unrelated).as_bytes())
+            .read(frame(&response).as_bytes())
+            .build();
+
+        let mut lsp_client = LspClient::from_stream(mock_server);
+        let error = lsp_client.expect_error(request).await.unwrap();
+        assert_eq!(error.code, LspErrorCode::InvalidParams);
+    }
+
+    #[tokio::test]
+    async fn test_notifications_are_routed() {
+        let telemetry = json!({
+            "jsonrpc": "2.0",
+            "method": "telemetry/event",
+            "params": { "event": "ready" }
+        })
+        .to_string();
+        let response = json!({ "jsonrpc": "2.0", "id": 1, "result": null }).to_string();
+
+        let mock_server = Builder::new()
+            .read(format!("Content-Length: {}\r\n\r\n{}", telemetry.len(), telemetry).as_bytes())
+            .read(format!("Content-Length: {}\r\n\r\n{}", response.len(), response).as_bytes())
+            .build();
+
+        let mut lsp_client = LspClient::from_stream(mock_server);
+        let mut notifications = lsp_client.notifications();
+
+        let response = lsp_client.handle_response().await.unwrap();
+        assert_eq!(response.id, Some(json!(1)));
+
+        let notification = notifications.try_recv().unwrap();
+        assert!(matches!(
+            ServerNotification::from(notification),
+            ServerNotification::TelemetryEvent(TelemetryEventParams(params)) if params == json!({ "event": "ready" })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_server_requests_are_answered() {
+        let configuration = json!({
+            "jsonrpc": "2.0",
+            "id": "config-1",
+            "method": "workspace/configuration",
+            "params": { "items": [{ "section": "gopls" }] }
+        })
+        .to_string();
+        let show_message = json!({
+            "jsonrpc": "2.0",
+            "id": 9,
+            "method": "window/showMessageRequest",
+            "params": { "type": 1, "message": "?" }
+        })
+        .to_string();
+        let response = json!({ "jsonrpc": "2.0", "id": 1, "result": null }).to_string();
+
+        let configuration_reply = serde_json::to_string(&ResponseMessage::new_result(
+            json!("config-1"),
+            json!([{ "staticcheck": true }]),
+        ))
+        .unwrap();
+        let show_message_reply = serde_json::to_string(&ResponseMessage::new_error(
+            json!(9),
+            ResponseError::new(
+                LspErrorCode::MethodNotFound,
+                "Unhandled method window/showMessageRequest",
+            ),
+        ))
+        .unwrap();
+
+        let frame = |body: &str| format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let mock_server = Builder::new()
+            .read(frame(&configuration).as_bytes())
+            .write(frame(&configuration_reply).as_bytes())
+            .read(frame(&show_message).as_bytes())
+            .write(frame(&show_message_reply).as_bytes())
+            .read(frame(&response).as_bytes())
+            .build();
+
+        let mut lsp_client = LspClient::from_stream(mock_server);
+        lsp_client.on_request("workspace/configuration", |params| {
+            assert_eq!(params["items"][0]["section"], "gopls");
+            Ok(json!([{ "staticcheck": true }]))
+        });
+
+        let response = lsp_client.handle_response().await.unwrap();
+        assert_eq!(response.id, Some(json!(1)));
+    }
+
+    #[tokio::test]
+    async fn test_reinitialize_replays_open_documents() {
+        let initialize = RequestMessage::new_initialize(
+            1,
+            Some(42),
+            "file:///tmp".into(),
+            "unit_test_client".into(),
+            Some("0.1.0".into()),
+            vec![],
+        );
+        let did_open = NotificationMessage::new_did_open(
+            "file:///tmp/main.go".into(),
+            "go".into(),
+            1,
+            "package main".into(),
+        );
+        let did_close = NotificationMessage::new_did_close("file:///tmp/other.go".into());
+
+        let frame = |body: &str| format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let initialize_json = serde_json::to_string(&initialize).unwrap();
+        let did_open_json = serde_json::to_string(&did_open).unwrap();
+        let did_close_json = serde_json::to_string(&did_close).unwrap();
+        let first_server = Builder::new()
+            .write(frame(&initialize_json).as_bytes())
+            .write(frame(&did_open_json).as_bytes())
+            .write(frame(&did_close_json).as_bytes())
+            .build();
+
+        let mut lsp_client = LspClient::from_stream(first_server);
+        lsp_client.send_request(initialize).await.unwrap();
+        lsp_client.send_request(did_open).await.unwrap();
+        lsp_client.send_request(did_close).await.unwrap();
+
+        // The replayed request gets a fresh id
+        let replayed_initialize = serde_json::to_string(&RequestMessage::new_initialize(
+            2,
+            Some(42),
+            "file:///tmp".into(),
+            "unit_test_client".into(),
+            Some("0.1.0".into()),
+            vec![],
+        ))
+        .unwrap();
+        let initialized = serde_json::to_string(&NotificationMessage::new_initialized()).unwrap();
+        let stale = json!({ "jsonrpc": "2.0", "id": 1, "result": null }).to_string();
+        let response =
+            json!({ "jsonrpc": "2.0", "id": 2, "result": { "capabilities": {} } }).to_string();
+        let restarted_server = Builder::new()
+            .write(frame(&replayed_initialize).as_bytes())
+            .read(frame(&stale).as_bytes())
+            .read(frame(&response).as_bytes())
+            .write(frame(&initialized).as_bytes())
+            .write(frame(&did_open_json).as_bytes())
+            .build();
+
+        lsp_client.replace_stream(restarted_server);
+        lsp_client.reinitialize().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reinitialize_refreshes_negotiated_capabilities() {
+        let initialize = RequestMessage::new_initialize(
+            1,
+            None,
+            "file:///tmp".into(),
+            "test".into(),
+            None,
+            vec![],
+        );
+        let mut lsp_client = crate::testing::MockServer::new().client();
+        lsp_client.initialize(initialize.clone()).await.unwrap();
+        assert_eq!(lsp_client.position_encoding(), PositionEncoding::Utf16);
+        assert_eq!(lsp_client.text_document_sync(), TextDocumentSyncKind::None);
+
+        // The restarted server picks a different encoding and sync kind
+        let mut replayed = initialize;
+        replayed.id = json!(2);
+        let frame = |body: &str| format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let response = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "result": { "capabilities": { "positionEncoding": "utf-8", "textDocumentSync": 1 } }
+        })
+        .to_string();
+        let initialized = serde_json::to_string(&NotificationMessage::new_initialized()).unwrap();
+        let restarted_server = Builder::new()
+            .write(frame(&serde_json::to_string(&replayed).unwrap()).as_bytes())
+            .read(frame(&response).as_bytes())
+            .write(frame(&initialized).as_bytes())
+            .build();
+
+        lsp_client.replace_stream(restarted_server);
+        lsp_client.reinitialize().await.unwrap();
+        assert_eq!(lsp_client.position_encoding(), PositionEncoding::Utf8);
+        assert_eq!(lsp_client.text_document_sync(), TextDocumentSyncKind::Full);
+    }
+
+    #[tokio::test]
+    async fn test_initialize_after_graceful_shutdown() {
+        let mut lsp_client = crate::testing::MockServer::new().client();
+        lsp_client
+            .graceful_shutdown(Duration::from_millis(100))
+            .await
+            .unwrap();
+
+        // `shutdown` used id 1
+        let initialize = RequestMessage::new_initialize(
+            2,
+            None,
+            "file:///tmp".into(),
+            "test".into(),
+            None,
+            vec![],
+        );
+        let frame = |body: &str| format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let response =
+            json!({ "jsonrpc": "2.0", "id": 2, "result": { "capabilities": {} } }).to_string();
+        let initialized = serde_json::to_string(&NotificationMessage::new_initialized()).unwrap();
+        let restarted_server = Builder::new()
+            .write(frame(&serde_json::to_string(&initialize).unwrap()).as_bytes())
+            .read(frame(&response).as_bytes())
+            .write(frame(&initialized).as_bytes())
+            .build();
+
+        lsp_client.replace_stream(restarted_server);
+        assert!(!lsp_client.is_closed());
+        lsp_client.initialize(initialize).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_request_shutdown_then_exit() {
+        let server = crate::testing::MockServer::new().on("textDocument/hover", |_| {
+            Err(ResponseError::new(
+                LspErrorCode::InvalidRequest,
+                "Server is shutting down".to_string(),
+            ))
+        });
+        let received = server.received();
+        let mut client = server.client();
+
+        client.request_shutdown().await.unwrap();
+        // The connection is still open between `shutdown` and `exit`
+        let hover =
+            RequestMessage::new_hover(10, "file:///tmp/main.go".into(), Position::new(0, 0));
+        let error = client.expect_error(hover).await.unwrap();
+        assert_eq!(error.code, LspErrorCode::InvalidRequest);
+
+        client.send_exit().await.unwrap();
+        assert!(client.is_closed());
+        // The server hangs up after `exit`
+        assert!(client.handle_response().await.is_err());
+        let methods: Vec<serde_json::Value> = received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|message| message["method"].clone())
+            .collect();
+        assert_eq!(methods, vec!["shutdown", "textDocument/hover", "exit"]);
+    }
+
+    #[tokio::test]
+    async fn test_graceful_shutdown() {
+        let (client_stream, server_stream) = tokio::io::duplex(4096);
+        let (mut server_read, mut server_write) = tokio::io::split(server_stream);
+
+        let server = tokio::spawn(async move {
+            let framing = ContentLength::default();
+            let mut methods = Vec::new();
+            for _ in 0..2 {
+                let request: serde_json::Value =
+                    serde_json::from_slice(&framing.decode(&mut server_read).await.unwrap())
+                        .unwrap();
+                methods.push(request["method"].as_str().unwrap().to_string());
+            }
+            // Only the first request is ever answered
+            let response = json!({ "jsonrpc": "2.0", "id": 1, "result": "done" }).to_string();
+            server_write
+                .write_all(&framing.encode(response.as_bytes()).unwrap())
+                .await
+                .unwrap();
+
+            let shutdown: serde_json::Value =
+                serde_json::from_slice(&framing.decode(&mut server_read).await.unwrap()).unwrap();
+            methods.push(shutdown["method"].as_str().unwrap().to_string());
+            let response =
+                json!({ "jsonrpc": "2.0", "id": shutdown["id"], "result": null }).to_string();
+            server_write
+                .write_all(&framing.encode(response.as_bytes()).unwrap())
+                .await
+                .unwrap();
+
+            let exit: serde_json::Value =
+                serde_json::from_slice(&framing.decode(&mut server_read).await.unwrap()).unwrap();
+            methods.push(exit["method"].as_str().unwrap().to_string());
+            methods
+        });
+
+        let mut lsp_client = LspClient::from_stream(client_stream);
+        lsp_client
+            .send_raw_request(1, "custom/fast", json!({}))
+            .await
+            .unwrap();
+        lsp_client
+            .send_raw_request(2, "custom/slow", json!({}))
+            .await
+            .unwrap();
+
+        let outcomes = lsp_client
+            .graceful_shutdown(Duration::from_millis(100))
+            .await
+            .unwrap();
+        assert_eq!(outcomes.len(), 2);
+        assert_eq!(outcomes[0].0, RequestId::Number(1));
+        assert_eq!(outcomes[0].1.as_ref().unwrap().result, Some(json!("done")));
+        assert_eq!(outcomes[1].0, RequestId::Number(2));
+        assert!(outcomes[1].1.as_ref().unwrap_err().is::<ShuttingDown>());
+
+        let rejected = lsp_client
+            .send_raw_request(4, "custom/late", json!({}))
+            .await;
+        assert!(rejected.unwrap_err().is::<ShuttingDown>());
+
+        assert_eq!(
+            server.await.unwrap(),
+            vec!["custom/fast", "custom/slow", "shutdown", "exit"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_with_partial_results() {
+        let request = RequestMessage::new_raw(
+            1,
+            "textDocument/references".into(),
+            json!({ "textDocument": { "uri": "file:///tmp/main.go" } }),
+        );
+        let sent = serde_json::to_string(
+            &RequestMessage::new_raw(
+                1,
+                "textDocument/references".into(),
+                json!({ "textDocument": { "uri": "file:///tmp/main.go" } }),
+            )
+            .with_partial_result_token(ProgressToken::Number(7)),
+        )
+        .unwrap();
+
+        let chunk = |value: serde_json::Value| {
+            json!({ "jsonrpc": "2.0", "method": "$/progress", "params": { "token": 7, "value": value } })
+                .to_string()
+        };
+        let first = chunk(json!([{ "uri": "file:///tmp/a.go" }]));
+        let second = chunk(json!([{ "uri": "file:///tmp/b.go" }]));
+        let response =
+            json!({ "jsonrpc": "2.0", "id": 1, "result": [{ "uri": "file:///tmp/c.go" }] })
+                .to_string();
+
+        let frame = |body: &str| format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let mock_server = Builder::new()
+            .write(frame(&sent).as_bytes())
+            .read(frame(&first).as_bytes())
+            .read(frame(&second).as_bytes())
+            .read(frame(&response).as_bytes())
+            .build();
+
+        let mut lsp_client = LspClient::from_stream(mock_server);
+        let streamed = lsp_client
+            .request_with_partial_results(request, ProgressToken::Number(7))
+            .await
+            .unwrap();
+
+        assert_eq!(streamed.partial_results.len(), 2);
+        let uris: Vec<_> = streamed
+            .assemble()
+            .unwrap()
+            .into_iter()
+            .map(|location| location["uri"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            uris,
+            vec!["file:///tmp/a.go", "file:///tmp/b.go", "file:///tmp/c.go"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_requests_are_acknowledged() {
+        let frame = |body: &str| format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let mut mock_server = Builder::new();
+        for (id, method) in [
+            (1, "workspace/semanticTokens/refresh"),
+            (2, "workspace/codeLens/refresh"),
+            (3, "workspace/inlayHint/refresh"),
+            (4, "workspace/diagnostic/refresh"),
+        ] {
+            let refresh = json!({ "jsonrpc": "2.0", "id": id, "method": method }).to_string();
+            let ack = serde_json::to_string(&ResponseMessage::new_result(
+                json!(id),
+                serde_json::Value::Null,
+            ))
+            .unwrap();
+            mock_server
+                .read(frame(&refresh).as_bytes())
+                .write(frame(&ack).as_bytes());
+        }
+        let response = json!({ "jsonrpc": "2.0", "id": 10, "result": null }).to_string();
+        let mock_server = mock_server.read(frame(&response).as_bytes()).build();
+
+        let mut lsp_client = LspClient::from_stream(mock_server);
+        let mut server_requests = lsp_client.server_requests();
+        lsp_client.handle_response().await.unwrap();
+
+        assert!(matches!(
+            ServerRequest::from(server_requests.try_recv().unwrap()),
+            ServerRequest::SemanticTokensRefresh
+        ));
+        assert!(matches!(
+            ServerRequest::from(server_requests.try_recv().unwrap()),
+            ServerRequest::CodeLensRefresh
+        ));
+        assert!(matches!(
+            ServerRequest::from(server_requests.try_recv().unwrap()),
+            ServerRequest::InlayHintRefresh
+        ));
+        assert!(matches!(
+            ServerRequest::from(server_requests.try_recv().unwrap()),
+            ServerRequest::DiagnosticRefresh
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_closed_signal() {
+        let (client_stream, server_stream) = tokio::io::duplex(64);
+        let mut lsp_client = LspClient::from_stream(client_stream);
+        let closed = tokio::spawn(lsp_client.closed());
+        assert!(!lsp_client.is_closed());
+
+        drop(server_stream);
+        assert!(lsp_client.handle_response().await.is_err());
+        assert!(lsp_client.is_closed());
+        tokio::time::timeout(Duration::from_secs(1), closed)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_send_notifications_single_write() {
+        let notifications = vec![
+            NotificationMessage::new_did_open(
+                "file:///tmp/main.go".into(),
+                "go".into(),
+                1,
+                "package main".into(),
+            ),
+            NotificationMessage::new_did_close("file:///tmp/main.go".into()),
+        ];
+        let expected: String = notifications
+            .iter()
+            .map(|notification| {
+                let body = serde_json::to_string(notification).unwrap();
+                format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+            })
+            .collect();
+
+        let mock_server = Builder::new().write(expected.as_bytes()).build();
+        let mut lsp_client = LspClient::from_stream(mock_server);
+        lsp_client.send_notifications(notifications).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_write_raw() {
+        let mock_server = Builder::new()
+            .write(b"Content-Length: 1000\r\n\r\n{")
+            .build();
+        let mut lsp_client = LspClient::from_stream(mock_server);
+        lsp_client
+            .write_raw(b"Content-Length: 1000\r\n\r\n{")
+            .await
+            .unwrap();
+    }
+
+    #[test]
+    fn test_spawn_on_handle() {
+        use tokio_stream::StreamExt;
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo 'ready' >&2"]);
+        // Not inside the runtime here
+        let mut lsp_client = LspClient::spawn_on(command, runtime.handle()).unwrap();
+
+        let stderr: Vec<String> = runtime.block_on(lsp_client.server_stderr().unwrap().collect());
+        assert_eq!(stderr, vec!["ready"]);
+    }
+
+    #[tokio::test]
+    async fn test_spawn_captures_stderr() {
+        use tokio_stream::StreamExt;
+
+        let mut command = Command::new("sh");
+        command.args(["-c", "echo 'loading workspace' >&2; echo 'panic: boom' >&2"]);
+        let mut lsp_client = LspClient::spawn(command).unwrap();
+
+        let stderr: Vec<String> = lsp_client.server_stderr().unwrap().collect().await;
+        assert_eq!(stderr, vec!["loading workspace", "panic: boom"]);
+        assert!(lsp_client.server_stderr().is_none());
+
+        let status = loop {
+            if let Some(status) = lsp_client.server_exit_status().unwrap() {
+                break status;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert!(status.success());
+    }
+
+    #[tokio::test]
+    async fn test_send_request_and_wait_checks_id() {
+        let request = RequestMessage::new_raw(2, "custom/echo".into(), json!({}));
+        let request_json = serde_json::to_string(&request).unwrap();
+        let stale = json!({ "jsonrpc": "2.0", "id": 1, "result": "stale" }).to_string();
+
+        let frame = |body: &str| format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let mock_server = Builder::new()
+            .write(frame(&request_json).as_bytes())
+            .read(frame(&stale).as_bytes())
+            .build();
+
+        let mut lsp_client = LspClient::from_stream(mock_server);
+        let error = lsp_client.send_request_and_wait(request).await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<ResponseIdMismatch>(),
+            Some(&ResponseIdMismatch {
+                expected: RequestId::Number(2),
+                actual: Some(json!(1)),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_work_done_progress_create() {
+        let frame = |body: &str| format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let create = |id: u32, token: &str| {
+            json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": "window/workDoneProgress/create",
+                "params": { "token": token }
+            })
+            .to_string()
+        };
+        let ack = |id: u32| {
+            serde_json::to_string(&ResponseMessage::new_result(
+                json!(id),
+                serde_json::Value::Null,
+            ))
+            .unwrap()
+        };
+        let progress = |token: &str, kind: &str| {
+            json!({
+                "jsonrpc": "2.0",
+                "method": "$/progress",
+                "params": { "token": token, "value": { "kind": kind, "title": "Indexing" } }
+            })
+            .to_string()
+        };
+        let response = json!({ "jsonrpc": "2.0", "id": 1, "result": null }).to_string();
+
+        let mock_server = Builder::new()
+            .read(frame(&create(100, "indexing")).as_bytes())
+            .write(frame(&ack(100)).as_bytes())
+            .read(frame(&create(101, "loading")).as_bytes())
+            .write(frame(&ack(101)).as_bytes())
+            .read(frame(&progress("indexing", "begin")).as_bytes())
+            .read(frame(&progress("loading", "begin")).as_bytes())
+            .read(frame(&progress("loading", "end")).as_bytes())
+            .read(frame(&response).as_bytes())
+            .build();
+
+        let mut lsp_client = LspClient::from_stream(mock_server);
+        let mut server_requests = lsp_client.server_requests();
+        lsp_client.handle_response().await.unwrap();
+
+        assert!(matches!(
+            ServerRequest::from(server_requests.try_recv().unwrap()),
+            ServerRequest::WorkDoneProgressCreate(params) if params.token == ProgressToken::String("indexing".into())
+        ));
+        assert!(lsp_client.has_progress_token(&ProgressToken::String("indexing".into())));
+        assert!(!lsp_client.has_progress_token(&ProgressToken::String("loading".into())));
+    }
+
+    #[tokio::test]
+    async fn test_messages_for_a_document_are_sent_in_order() {
+        let did_change = NotificationMessage::new_did_change(
+            "file:///tmp/main.go".into(),
+            2,
+            vec![TextDocumentContentChangeEvent {
+                range: None,
+                text: "package main\n\nfunc main() {}\n".into(),
+            }],
+        );
+        let hover = RequestMessage::new_hover(5, "file:///tmp/main.go".into(), Position::new(2, 5));
+
+        let frame = |body: String| format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let mock_server = Builder::new()
+            .write(frame(serde_json::to_string(&did_change).unwrap()).as_bytes())
+            .write(frame(serde_json::to_string(&hover).unwrap()).as_bytes())
+            .build();
+
+        let mut lsp_client = LspClient::from_stream(mock_server);
+        lsp_client.send_request(did_change).await.unwrap();
+        lsp_client.send_request(hover).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pretty_logging_keeps_the_wire_compact() {
+        let initialize = RequestMessage::new_initialize(
+            1,
+            None,
+            "file:///tmp".into(),
+            "test".into(),
+            None,
+            vec![],
+        );
+        let body = serde_json::to_string(&initialize).unwrap();
+        let mock_server = Builder::new()
+            .write(format!("Content-Length: {}\r\n\r\n{}", body.len(), body).as_bytes())
+            .build();
+
+        let mut lsp_client = LspClient::from_stream(mock_server).with_pretty_logging(true);
+        lsp_client.send_request(initialize).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_messages_are_logged_at_trace_level() {
+        struct Capture(Mutex<Vec<String>>);
+        impl log::Log for Capture {
+            fn enabled(&self, metadata: &log::Metadata) -> bool {
+                metadata.level() == log::Level::Trace
+            }
+            fn log(&self, record: &log::Record) {
+                self.0.lock().unwrap().push(record.args().to_string());
+            }
+            fn flush(&self) {}
+        }
+        static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+        log::set_logger(&CAPTURE).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let request = RequestMessage::new_raw(4242, "test/trace".to_string(), json!({}));
+        let body = serde_json::to_string(&request).unwrap();
+        let response = r#"{"jsonrpc":"2.0","id":4242,"result":null}"#;
+        let mock_server = Builder::new()
+            .write(format!("Content-Length: {}\r\n\r\n{}", body.len(), body).as_bytes())
+            .read(format!("Content-Length: {}\r\n\r\n{}", response.len(), response).as_bytes())
+            .build();
+        let mut lsp_client = LspClient::from_stream(mock_server).with_pretty_logging(true);
+        lsp_client.send_request_and_wait(request).await.unwrap();
+
+        let logged: Vec<String> = CAPTURE
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|message| message.contains("4242"))
+            .cloned()
+            .collect();
+        assert_eq!(logged.len(), 2);
+        assert!(logged[0].starts_with("Sent {\n"));
+        assert!(logged[0].contains("\"method\": \"test/trace\""));
+        assert!(logged[1].starts_with("Received {\n"));
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat() {
+        let heartbeat = Heartbeat {
+            interval: Duration::ZERO,
+            timeout: Duration::from_millis(50),
+            ..Heartbeat::default()
+        };
+        let (client_stream, server_stream) = tokio::io::duplex(1024);
+        let mut lsp_client = LspClient::from_stream(client_stream).with_heartbeat(heartbeat);
+        let (server_read, mut server_write) = tokio::io::split(server_stream);
+        let mut server_read = BufReader::new(server_read);
+
+        // A server answering the probe, even with an error, is alive
+        let error = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "error": { "code": -32601, "message": "Unhandled method $/lsp-client-rs/ping" }
+        })
+        .to_string();
+        server_write
+            .write_all(format!("Content-Length: {}\r\n\r\n{}", error.len(), error).as_bytes())
+            .await
+            .unwrap();
+        lsp_client.check_liveness().await.unwrap();
+        let probe = ContentLength::default()
+            .decode(&mut server_read)
+            .await
+            .unwrap();
+        let probe: serde_json::Value = serde_json::from_slice(&probe).unwrap();
+        assert_eq!(probe["method"], "$/lsp-client-rs/ping");
+        assert!(!lsp_client.is_closed());
+
+        // A server that stops answering is flagged as dead
+        assert!(lsp_client.check_liveness().await.is_err());
+        assert!(lsp_client.is_closed());
+    }
+
+    #[tokio::test]
+    async fn test_deterministic_request_ids() {
+        let initialize = |id| {
+            RequestMessage::new_initialize(
+                id,
+                Some(1234),
+                "file:///tmp".into(),
+                "test".into(),
+                None,
+                vec![],
+            )
+        };
+        let body = serde_json::to_string(&initialize(100)).unwrap();
+        let mock_server = Builder::new()
+            .write(format!("Content-Length: {}\r\n\r\n{}", body.len(), body).as_bytes())
+            .build();
+
+        let mut lsp_client = LspClient::from_stream(mock_server).with_id_start(100);
+        let id = lsp_client.next_request_id();
+        assert_eq!(id, 100);
+        lsp_client.send_request(initialize(id)).await.unwrap();
+        assert_eq!(lsp_client.next_request_id(), 101);
+    }
+
+    /// Reads the next message written by the client on the server side of a pipe.
+    async fn read_client_message(
+        reader: &mut (impl AsyncRead + Unpin + Send),
+    ) -> serde_json::Value {
+        let body = ContentLength::default().decode(reader).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_initialize_reports_attempts() {
+        let (client_stream, _server_stream) = tokio::io::duplex(4096);
+        let mut lsp_client =
+            LspClient::from_stream(client_stream).with_initialize_options(InitializeOptions {
+                timeout: Duration::from_millis(20),
+                retries: 1,
+            });
+        let request = RequestMessage::new_initialize(
+            1,
+            None,
+            "file:///tmp".into(),
+            "test".into(),
+            None,
+            vec![],
+        );
+        let err = lsp_client.initialize(request).await.unwrap_err();
+        assert_eq!(err.to_string(), "Initialize failed after 2 attempt(s)");
+        assert!(err.root_cause().to_string().starts_with("Timed out"));
+    }
+
+    #[tokio::test]
+    async fn test_cancellable_server_request() {
+        let (client_stream, server_stream) = tokio::io::duplex(4096);
+        let mut lsp_client = LspClient::from_stream(client_stream);
+        lsp_client.on_request_cancellable("workspace/configuration", |_, token| async move {
+            token.cancelled().await;
+            Err(ResponseError::new(
+                LspErrorCode::RequestCancelled,
+                "cancelled".to_string(),
+            ))
+        });
+
+        let server = tokio::spawn(async move {
+            let (server_read, mut server_write) = tokio::io::split(server_stream);
+            let mut server_read = BufReader::new(server_read);
+            let frame = |body: serde_json::Value| {
+                let body = body.to_string();
+                format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+            };
+            let request = read_client_message(&mut server_read).await;
+            let configuration = json!({
+                "jsonrpc": "2.0",
+                "id": "c1",
+                "method": "workspace/configuration",
+                "params": { "items": [] }
+            });
+            let cancel = json!({
+                "jsonrpc": "2.0",
+                "method": "$/cancelRequest",
+                "params": { "id": "c1" }
+            });
+            for message in [configuration, cancel] {
+                server_write
+                    .write_all(frame(message).as_bytes())
+                    .await
+                    .unwrap();
+            }
+
+            // The handler is aborted and answers before the server answers the client
+            let answer = read_client_message(&mut server_read).await;
+            assert_eq!(answer["id"], "c1");
+            assert_eq!(answer["error"]["code"], -32800);
+            let response = json!({ "jsonrpc": "2.0", "id": request["id"], "result": null });
+            server_write
+                .write_all(frame(response).as_bytes())
+                .await
+                .unwrap();
+        });
+
+        let response = lsp_client
+            .send_request_and_wait(RequestMessage::new_shutdown(1))
+            .await
+            .unwrap();
+        assert_eq!(response.id, Some(json!(1)));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replace_stream_drops_unanswered_server_requests() {
+        let (client_stream, server_stream) = tokio::io::duplex(4096);
+        let mut lsp_client = LspClient::from_stream(client_stream);
+        lsp_client.on_request_cancellable("workspace/configuration", |_, _| async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Ok(json!([]))
+        });
+
+        let (server_read, mut server_write) = tokio::io::split(server_stream);
+        let frame = |body: serde_json::Value| {
+            let body = body.to_string();
+            format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+        };
+        let configuration = json!({
+            "jsonrpc": "2.0",
+            "id": "c1",
+            "method": "workspace/configuration",
+            "params": { "items": [] }
+        });
+        let response = json!({ "jsonrpc": "2.0", "id": 1, "result": null });
+        for message in [configuration, response] {
+            server_write
+                .write_all(frame(message).as_bytes())
+                .await
+                .unwrap();
+        }
+        lsp_client
+            .send_request_and_wait(RequestMessage::new_shutdown(1))
+            .await
+            .unwrap();
+        drop((server_read, server_write));
+
+        // The restarted server never asked, so it must not get the answer
+        let (client_stream, server_stream) = tokio::io::duplex(4096);
+        lsp_client.replace_stream(client_stream);
+        let (server_read, mut server_write) = tokio::io::split(server_stream);
+        let mut server_read = BufReader::new(server_read);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let configuration = json!({
+            "jsonrpc": "2.0",
+            "id": "c2",
+            "method": "workspace/configuration",
+            "params": { "items": [] }
+        });
+        let response = json!({ "jsonrpc": "2.0", "id": 2, "result": null });
+        for message in [configuration, response] {
+            server_write
+                .write_all(frame(message).as_bytes())
+                .await
+                .unwrap();
+        }
+        lsp_client
+            .send_request_and_wait(RequestMessage::new_shutdown(2))
+            .await
+            .unwrap();
+        lsp_client.send_exit().await.unwrap();
+
+        assert_eq!(read_client_message(&mut server_read).await["id"], 2);
+        assert_eq!(
+            read_client_message(&mut server_read).await["method"],
+            "exit"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_initialize_retries() {
+        let (client_stream, server_stream) = tokio::io::duplex(4096);
+        let mut lsp_client =
+            LspClient::from_stream(client_stream).with_initialize_options(InitializeOptions {
+                timeout: Duration::from_millis(50),
+                retries: 1,
+            });
+
+        let server = tokio::spawn(async move {
+            let (server_read, mut server_write) = tokio::io::split(server_stream);
+            let mut server_read = BufReader::new(server_read);
+            // The cold server misses the first attempt and answers the retry
+            let first = read_client_message(&mut server_read).await;
+            let second = read_client_message(&mut server_read).await;
+            assert_eq!(first["method"], "initialize");
+            assert_ne!(first["id"], second["id"]);
+
+            let response =
+                json!({ "jsonrpc": "2.0", "id": second["id"], "result": { "capabilities": {} } })
+                    .to_string();
+            server_write
+                .write_all(
+                    format!("Content-Length: {}\r\n\r\n{}", response.len(), response).as_bytes(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(
+                read_client_message(&mut server_read).await["method"],
+                "initialized"
+            );
+        });
+
+        let request = RequestMessage::new_initialize(
+            lsp_client.next_request_id(),
+            None,
+            "file:///tmp".into(),
+            "test".into(),
+            None,
+            vec![],
+        );
+        lsp_client.initialize(request).await.unwrap();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_notification_replay() {
+        let request = RequestMessage::new_shutdown(1);
+        let request_str = serde_json::to_string(&request).unwrap();
+        let frame = |body: &str| format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let log = |message: &str| {
+            json!({
+                "jsonrpc": "2.0",
+                "method": "window/logMessage",
+                "params": { "type": 3, "message": message }
+            })
+            .to_string()
+        };
+        let response = json!({ "jsonrpc": "2.0", "id": 1, "result": null }).to_string();
+        let mock_server = Builder::new()
+            .write(frame(&request_str).as_bytes())
+            .read(frame(&log("first")).as_bytes())
+            .read(frame(&log("second")).as_bytes())
+            .read(frame(&log("third")).as_bytes())
+            .read(frame(&response).as_bytes())
+            .read(frame(&log("fourth")).as_bytes())
+            .build();
+
+        let mut lsp_client = LspClient::from_stream(mock_server).with_notification_replay(2);
+        lsp_client.send_request(request).await.unwrap();
+        lsp_client.handle_response().await.unwrap();
+
+        // Subscribing late still sees the last two notifications, then new ones
+        let mut notifications = lsp_client.notifications();
+        assert!(lsp_client.read_message().await.unwrap().is_none());
+        let messages: Vec<serde_json::Value> = std::iter::from_fn(|| notifications.try_recv().ok())
+            .map(|notification| notification.params["message"].clone())
+            .collect();
+        assert_eq!(messages, vec!["second", "third", "fourth"]);
+    }
+
+    #[tokio::test]
+    async fn test_assert_drained() {
+        let mut lsp_client = LspClient::from_stream(Builder::new().build());
+        lsp_client.assert_drained().await.unwrap();
+
+        let request = RequestMessage::new_shutdown(1);
+        let unanswered = RequestMessage::new_shutdown(2);
+        let frame = |body: &str| format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let log = json!({
+            "jsonrpc": "2.0",
+            "method": "window/logMessage",
+            "params": { "type": 3, "message": "Loading" }
+        })
+        .to_string();
+        let diagnostics = json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": "file:///tmp/main.go", "diagnostics": [] }
+        })
+        .to_string();
+        let response = json!({ "jsonrpc": "2.0", "id": 1, "result": null }).to_string();
+        let mock_server = Builder::new()
+            .write(frame(&serde_json::to_string(&request).unwrap()).as_bytes())
+            .read(frame(&log).as_bytes())
+            .read(frame(&response).as_bytes())
+            .read(frame(&diagnostics).as_bytes())
+            .write(frame(&serde_json::to_string(&unanswered).unwrap()).as_bytes())
+            .build();
+
+        let mut lsp_client = LspClient::from_stream(mock_server);
+        let mut notifications = lsp_client.notifications();
+        lsp_client.send_request_and_wait(request).await.unwrap();
+        lsp_client.send_request(unanswered).await.unwrap();
+
+        let err = lsp_client.assert_drained().await.unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "The client is not drained:\n\
+             request 2 (shutdown) was never answered\n\
+             textDocument/publishDiagnostics from the server was not read\n\
+             1 notifications were not received by a subscriber"
+        );
+
+        // Reading the notification and the diagnostics left only the pending request
+        notifications.try_recv().unwrap();
+        let err = lsp_client.assert_drained().await.unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "The client is not drained:\nrequest 2 (shutdown) was never answered"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_orphaned_responses() {
+        let request = RequestMessage::new_shutdown(1);
+        let request_str = serde_json::to_string(&request).unwrap();
+        let frame = |body: &str| format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let response = json!({ "jsonrpc": "2.0", "id": 1, "result": null }).to_string();
+        let mock_server = Builder::new()
+            .write(frame(&request_str).as_bytes())
+            .read(frame(&response).as_bytes())
+            .read(frame(&response).as_bytes())
+            .build();
+
+        let mut lsp_client = LspClient::from_stream(mock_server);
+        let mut orphaned = lsp_client.orphaned_responses();
+        lsp_client.send_request(request).await.unwrap();
+
+        lsp_client.handle_response().await.unwrap();
+        assert!(orphaned.try_recv().is_err());
+
+        // The server answers the same request twice
+        lsp_client.handle_response().await.unwrap();
+        assert_eq!(orphaned.try_recv().unwrap().id, Some(json!(1)));
+    }
+
+    #[tokio::test]
+    async fn test_initialize_with_interleaved_messages() {
+        let request = RequestMessage::new_initialize(
+            1,
+            None,
+            "file:///tmp".into(),
+            "test".into(),
+            None,
+            vec![],
+        );
+        let frame = |body: String| format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let log_message = json!({
+            "jsonrpc": "2.0",
+            "method": "window/logMessage",
+            "params": { "type": 3, "message": "Loading workspace" }
+        });
+        let progress_create = json!({
+            "jsonrpc": "2.0",
+            "id": "p1",
+            "method": "window/workDoneProgress/create",
+            "params": { "token": "indexing" }
+        });
+        let ack = ResponseMessage::new_result(json!("p1"), serde_json::Value::Null);
+        let response = json!({ "jsonrpc": "2.0", "id": 1, "result": { "capabilities": {} } });
+        let mock_server = Builder::new()
+            .write(frame(serde_json::to_string(&request).unwrap()).as_bytes())
+            .read(frame(log_message.to_string()).as_bytes())
+            .read(frame(progress_create.to_string()).as_bytes())
+            .write(frame(serde_json::to_string(&ack).unwrap()).as_bytes())
+            .read(frame(response.to_string()).as_bytes())
+            .write(
+                frame(serde_json::to_string(&NotificationMessage::new_initialized()).unwrap())
+                    .as_bytes(),
+            )
+            .build();
+
+        let mut lsp_client = LspClient::from_stream(mock_server);
+        let mut notifications = lsp_client.notifications();
+        lsp_client.initialize(request).await.unwrap();
+
+        assert_eq!(
+            notifications.try_recv().unwrap().method,
+            "window/logMessage"
+        );
+        assert!(lsp_client.has_progress_token(&ProgressToken::String("indexing".into())));
+    }
+
+    #[tokio::test]
+    async fn test_document_versions() {
+        let uri = "file:///tmp/main.go";
+        let did_open =
+            NotificationMessage::new_did_open(uri.into(), "go".into(), 1, "package main".into());
+        let change = |text: &str| TextDocumentContentChangeEvent {
+            range: None,
+            text: text.into(),
+        };
+        let frame = |message: &NotificationMessage| {
+            let body = serde_json::to_string(message).unwrap();
+            format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+        };
+        let mock_server = Builder::new()
+            .write(frame(&did_open).as_bytes())
+            .write(
+                frame(&NotificationMessage::new_did_change(
+                    uri.into(),
+                    2,
+                    vec![change("a")],
+                ))
+                .as_bytes(),
+            )
+            .write(
+                frame(&NotificationMessage::new_did_change(
+                    uri.into(),
+                    3,
+                    vec![change("b")],
+                ))
+                .as_bytes(),
+            )
+            .build();
+
+        let mut lsp_client = LspClient::from_stream(mock_server);
+        assert_eq!(lsp_client.next_version(uri), None);
+        assert!(lsp_client
+            .send_did_change(uri, vec![change("a")])
+            .await
+            .is_err());
+
+        lsp_client.send_request(did_open).await.unwrap();
+        assert_eq!(lsp_client.next_version(uri), Some(2));
+        assert_eq!(
+            lsp_client
+                .send_did_change(uri, vec![change("a")])
+                .await
+                .unwrap(),
+            2
+        );
+        assert_eq!(
+            lsp_client
+                .send_did_change(uri, vec![change("b")])
+                .await
+                .unwrap(),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn test_await_progress_end() {
+        let frame = |body: String| format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let create = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "window/workDoneProgress/create",
+            "params": { "token": "indexing" }
+        });
+        let ack = ResponseMessage::new_result(json!(1), serde_json::Value::Null);
+        let progress = |value: serde_json::Value| {
+            json!({
+                "jsonrpc": "2.0",
+                "method": "$/progress",
+                "params": { "token": "indexing", "value": value }
+            })
+            .to_string()
+        };
+        let mock_server = Builder::new()
+            .read(frame(create.to_string()).as_bytes())
+            .write(frame(serde_json::to_string(&ack).unwrap()).as_bytes())
+            .read(frame(progress(json!({ "kind": "begin", "title": "Indexing" }))).as_bytes())
+            .read(frame(progress(json!({ "kind": "report", "percentage": 50 }))).as_bytes())
+            .read(frame(progress(json!({ "kind": "end" }))).as_bytes())
+            .build();
+
+        let mut lsp_client = LspClient::from_stream(mock_server);
+        let token = ProgressToken::String("indexing".into());
+        lsp_client
+            .await_progress_end(&token, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert!(!lsp_client.has_progress_token(&token));
+
+        // Already ended, so nothing more is read
+        lsp_client
+            .await_progress_end(&token, Duration::from_secs(1))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_execute_command_progress() {
+        let (client_stream, server_stream) = tokio::io::duplex(4096);
+        let mut lsp_client = LspClient::from_stream(client_stream);
+
+        let server = tokio::spawn(async move {
+            let (server_read, mut server_write) = tokio::io::split(server_stream);
+            let mut server_read = BufReader::new(server_read);
+            let request = read_client_message(&mut server_read).await;
+            let token = request["params"]["workDoneToken"].clone();
+            let messages = [
+                json!({ "jsonrpc": "2.0", "method": "$/progress", "params": { "token": token, "value": { "kind": "begin", "title": "Reindexing", "percentage": 0 } } }),
+                json!({ "jsonrpc": "2.0", "method": "$/progress", "params": { "token": token, "value": { "kind": "report", "percentage": 60 } } }),
+                json!({ "jsonrpc": "2.0", "method": "$/progress", "params": { "token": token, "value": { "kind": "end" } } }),
+                json!({ "jsonrpc": "2.0", "id": request["id"], "result": null }),
+            ];
+            for message in messages {
+                let body = message.to_string();
+                server_write
+                    .write_all(format!("Content-Length: {}\r\n\r\n{}", body.len(), body).as_bytes())
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let token = ProgressToken::String("reindex-1".to_string());
+        let execute = RequestMessage::new_execute_command(
+            1,
+            "project.reindex".to_string(),
+            vec![],
+            Some(token.clone()),
+        );
+        lsp_client.send_request(execute).await.unwrap();
+        assert!(lsp_client.has_progress_token(&token));
+
+        lsp_client
+            .await_progress_percentage(&token, 50, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(lsp_client.progress_percentage(&token), Some(60));
+        lsp_client
+            .await_progress_end(&token, Duration::from_secs(1))
+            .await
+            .unwrap();
+        let response = lsp_client.handle_response().await.unwrap();
+        assert_eq!(response.id, Some(json!(1)));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_await_progress_percentage() {
+        let frame = |body: String| format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let create = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "window/workDoneProgress/create",
+            "params": { "token": 7 }
+        });
+        let ack = ResponseMessage::new_result(json!(1), serde_json::Value::Null);
+        let progress = |value: serde_json::Value| {
+            json!({ "jsonrpc": "2.0", "method": "$/progress", "params": { "token": 7, "value": value } })
+                .to_string()
+        };
+        let mock_server = Builder::new()
+            .read(frame(create.to_string()).as_bytes())
+            .write(frame(serde_json::to_string(&ack).unwrap()).as_bytes())
+            .read(
+                frame(progress(
+                    json!({ "kind": "begin", "title": "Indexing", "percentage": 0 }),
+                ))
+                .as_bytes(),
+            )
+            .read(
+                frame(progress(
+                    json!({ "kind": "report", "message": "3/10", "percentage": 30 }),
+                ))
+                .as_bytes(),
+            )
+            .read(
+                frame(progress(
+                    json!({ "kind": "report", "message": "9/10", "percentage": 90 }),
+                ))
+                .as_bytes(),
+            )
+            .build();
+
+        let mut lsp_client = LspClient::from_stream(mock_server);
+        let mut notifications = lsp_client.notifications();
+        let token = ProgressToken::Number(7);
+        lsp_client
+            .await_progress_percentage(&token, 80, Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(lsp_client.progress_percentage(&token), Some(90));
+
+        // The reports are decoded from the notification stream as well
+        let mut percentages = Vec::new();
+        while let Ok(notification) = notifications.try_recv() {
+            if let ServerNotification::Progress(progress) = ServerNotification::from(notification) {
+                percentages.push(progress.work_done().unwrap().percentage().unwrap());
+            }
+        }
+        assert_eq!(percentages, vec![0, 30, 90]);
+    }
+
+    #[tokio::test]
+    async fn test_hover_in_unadvertised_format() {
+        let mut lsp_client = crate::testing::MockServer::new()
+            .expect(
+                "textDocument/hover",
+                json!({ "contents": { "kind": "markdown", "value": "**main**" } }),
+            )
+            .client();
+        assert!(lsp_client.hover_content_format().is_empty());
+
+        let initialize = RequestMessage::new_initialize(
+            1,
+            None,
+            "file:///tmp".into(),
+            "test".into(),
+            None,
+            vec![],
+        );
+        lsp_client.initialize(initialize).await.unwrap();
+        assert_eq!(lsp_client.hover_content_format(), ["plaintext"]);
+
+        // The server answers in markdown although the client only advertised plaintext
+        let hover = RequestMessage::new_hover(2, "file:///tmp/main.go".into(), Position::new(0, 0));
+        let hover = lsp_client
+            .send_request_and_wait(hover)
+            .await
+            .unwrap()
+            .handle_hover()
+            .unwrap()
+            .unwrap();
+        assert_eq!(hover.format(), MarkupKind::Markdown);
+        assert!(!lsp_client
+            .hover_content_format()
+            .iter()
+            .any(|format| format == hover.format().as_str()));
+    }
+
+    #[tokio::test]
+    async fn test_stale_requests_are_cancelled() {
+        let server = crate::testing::MockServer::new();
+        let received = server.received();
+        let mut lsp_client = server.client();
+
+        let initialize = RequestMessage::new_initialize_with_capabilities(
+            1,
+            None,
+            "file:///tmp".into(),
+            "test".into(),
+            None,
+            vec![],
+            ClientCapabilities::default().with_stale_request_support(true, vec![]),
+        );
+        lsp_client.initialize(initialize).await.unwrap();
+
+        let uri = "file:///tmp/main.go";
+        let other = "file:///tmp/other.go";
+        for (uri, text) in [(uri, "package main"), (other, "package other")] {
+            let did_open =
+                NotificationMessage::new_did_open(uri.into(), "go".into(), 1, text.into());
+            lsp_client.send_request(did_open).await.unwrap();
+        }
+        for (id, uri) in [(5, uri), (2, uri), (3, other)] {
+            let hover = RequestMessage::new_hover(id, uri.into(), Position::new(0, 0));
+            lsp_client.send_request(hover).await.unwrap();
+        }
+        // The second change does not cancel the requests again
+        for _ in 0..2 {
+            let change = TextDocumentContentChangeEvent {
+                range: None,
+                text: "package main\n".into(),
+            };
+            lsp_client.send_did_change(uri, vec![change]).await.unwrap();
+        }
+
+        // Cancelled requests are still answered; once `shutdown` is answered as well, the
+        // server has seen everything sent before it
+        lsp_client
+            .send_request(RequestMessage::new_shutdown(6))
+            .await
+            .unwrap();
+        for _ in 0..4 {
+            lsp_client.handle_response().await.unwrap();
+        }
+        let cancelled: Vec<serde_json::Value> = received
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|message| message["method"] == "$/cancelRequest")
+            .map(|message| message["params"]["id"].clone())
+            .collect();
+        assert_eq!(cancelled, vec![json!(2), json!(5)]);
+    }
+
+    #[tokio::test]
+    async fn test_retry_on_content_modified() {
+        let attempts = Arc::new(AtomicU32::new(0));
+        let counter = attempts.clone();
+        let server = crate::testing::MockServer::new().on("textDocument/hover", move |_| {
+            if counter.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(ResponseError::new(
+                    LspErrorCode::ContentModified,
+                    "content modified",
+                ))
+            } else {
+                Ok(json!({ "contents": "fresh" }))
+            }
+        });
+        let mut lsp_client = server.client().with_retry_on_content_modified(2);
+
+        let uri = "file:///tmp/main.go".to_string();
+        let hover = RequestMessage::new_hover(1, uri.clone(), Position::new(0, 0));
+        let response = lsp_client.send_request_and_wait(hover).await.unwrap();
+        assert_eq!(response.result, Some(json!({ "contents": "fresh" })));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        // Without retries the error is returned as is
+        let mut lsp_client = lsp_client.with_retry_on_content_modified(0);
+        attempts.store(0, Ordering::SeqCst);
+        let hover =
+            RequestMessage::new_hover(lsp_client.next_request_id(), uri, Position::new(0, 0));
+        let response = lsp_client.send_request_and_wait(hover).await.unwrap();
+        assert_eq!(response.error.unwrap().code, LspErrorCode::ContentModified);
+    }
+
+    #[tokio::test]
+    async fn test_pending_requests() {
+        let mut lsp_client = crate::testing::MockServer::new()
+            .expect("shutdown", serde_json::Value::Null)
+            .client();
+        assert!(lsp_client.pending_requests().is_empty());
+
+        let uri = "file:///tmp/main.go".to_string();
+        let hover = RequestMessage::new_hover(1, uri.clone(), Position::new(0, 0));
+        lsp_client.send_request(hover).await.unwrap();
+        let definition = RequestMessage::new_get_definition(2, uri, Position::new(0, 0));
+        lsp_client.send_request(definition).await.unwrap();
+        lsp_client
+            .send_request(NotificationMessage::new_initialized())
+            .await
+            .unwrap();
+        assert_eq!(
+            lsp_client.pending_requests(),
+            vec![
+                (RequestId::Number(1), "textDocument/hover".to_string()),
+                (RequestId::Number(2), "textDocument/definition".to_string()),
+            ]
+        );
+
+        lsp_client.handle_response().await.unwrap();
+        assert_eq!(
+            lsp_client.pending_requests(),
+            vec![(RequestId::Number(2), "textDocument/definition".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_text_document_sync() {
+        let mut lsp_client = crate::testing::MockServer::new()
+            .expect(
+                "initialize",
+                json!({ "capabilities": { "textDocumentSync": { "openClose": true, "change": 2 } } }),
+            )
+            .client();
+        assert_eq!(lsp_client.text_document_sync(), TextDocumentSyncKind::None);
+
+        let initialize = RequestMessage::new_initialize(
+            1,
+            None,
+            "file:///tmp".into(),
+            "test".into(),
+            None,
+            vec![],
+        );
+        lsp_client.initialize(initialize).await.unwrap();
+        assert_eq!(
+            lsp_client.text_document_sync(),
+            TextDocumentSyncKind::Incremental
+        );
+    }
+
+    #[tokio::test]
+    async fn test_complete_trigger_characters() {
+        let mut lsp_client = crate::testing::MockServer::new()
+            .expect(
+                "initialize",
+                json!({ "capabilities": { "completionProvider": { "triggerCharacters": [".", ":"] } } }),
+            )
+            .on("textDocument/completion", |params| {
+                assert_eq!(params["context"]["triggerKind"], json!(2));
+                let label = format!("after {}", params["context"]["triggerCharacter"].as_str().unwrap());
+                Ok(json!([{ "label": label }]))
+            })
+            .client();
+
+        let initialize = RequestMessage::new_initialize(
+            lsp_client.next_request_id(),
+            None,
+            "file:///tmp".into(),
+            "test".into(),
+            None,
+            vec![],
+        );
+        let result = lsp_client.initialize(initialize).await.unwrap();
+        let completions = lsp_client
+            .complete_trigger_characters(
+                &result.capabilities,
+                "file:///tmp/main.go",
+                Position::new(3, 4),
+            )
+            .await
+            .unwrap();
+        let labels: Vec<(&str, &str)> = completions
+            .iter()
+            .map(|(trigger, list)| (trigger.as_str(), list.items[0].label.as_str()))
+            .collect();
+        assert_eq!(labels, vec![(".", "after ."), (":", "after :")]);
+
+        let completions = lsp_client
+            .complete_trigger_characters(
+                &ServerCapabilities::default(),
+                "file:///tmp/main.go",
+                Position::new(3, 4),
+            )
+            .await
+            .unwrap();
+        assert!(completions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_join_responses() {
+        let (client_stream, server_stream) = tokio::io::duplex(4096);
+        let mut lsp_client = LspClient::from_stream(client_stream);
+
+        let server = tokio::spawn(async move {
+            let (server_read, mut server_write) = tokio::io::split(server_stream);
+            let mut server_read = BufReader::new(server_read);
+            // Both requests are in flight before either is answered
+            let first = read_client_message(&mut server_read).await;
+            let second = read_client_message(&mut server_read).await;
+            for request in [second, first] {
+                let response =
+                    json!({ "jsonrpc": "2.0", "id": request["id"], "result": request["method"] })
+                        .to_string();
+                server_write
+                    .write_all(
+                        format!("Content-Length: {}\r\n\r\n{}", response.len(), response)
+                            .as_bytes(),
+                    )
+                    .await
+                    .unwrap();
+            }
+        });
+
+        let uri = "file:///tmp/main.go".to_string();
+        let hover = lsp_client
+            .send_request_async(RequestMessage::new_hover(
+                1,
+                uri.clone(),
+                Position::new(0, 0),
+            ))
+            .await
+            .unwrap();
+        let definition = lsp_client
+            .send_request_async(RequestMessage::new_get_definition(
+                2,
+                uri,
+                Position::new(0, 0),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(definition.id(), &RequestId::Number(2));
+
+        let responses = lsp_client
+            .join_responses(&[hover, definition])
+            .await
+            .unwrap();
+        let results: Vec<serde_json::Value> = responses
+            .into_iter()
+            .map(|response| response.result.unwrap())
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                json!("textDocument/hover"),
+                json!("textDocument/definition")
+            ]
+        );
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_show_document_requests() {
+        let show_document = |id: u32| {
+            json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": "window/showDocument",
+                "params": {
+                    "uri": "file:///tmp/generated.go",
+                    "takeFocus": true,
+                    "selection": {
+                        "start": { "line": 2, "character": 0 },
+                        "end": { "line": 2, "character": 4 },
+                    },
+                }
+            })
+            .to_string()
+        };
+        let reply = |id: u32, success: bool| {
+            serde_json::to_string(&ResponseMessage::new_result(
+                json!(id),
+                json!({ "success": success }),
+            ))
+            .unwrap()
+        };
+        let response = json!({ "jsonrpc": "2.0", "id": 1, "result": null }).to_string();
+
+        let frame = |body: &str| format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let mock_server = Builder::new()
+            .read(frame(&show_document(7)).as_bytes())
+            .write(frame(&reply(7, false)).as_bytes())
+            .read(frame(&response).as_bytes())
+            .read(frame(&show_document(8)).as_bytes())
+            .write(frame(&reply(8, true)).as_bytes())
+            .read(frame(&response).as_bytes())
+            .build();
+
+        let mut lsp_client = LspClient::from_stream(mock_server);
+        let mut server_requests = lsp_client.server_requests();
+        // Without a handler the server is told nothing was shown
+        lsp_client.handle_response().await.unwrap();
+        match ServerRequest::from(server_requests.try_recv().unwrap()) {
+            ServerRequest::ShowDocument(params) => {
+                assert_eq!(params.uri, "file:///tmp/generated.go");
+                assert_eq!(params.take_focus, Some(true));
+                assert_eq!(params.external, None);
+                assert_eq!(params.selection.unwrap().start, Position::new(2, 0));
+            }
+            other => panic!("Unexpected server request {:?}", other),
+        }
+
+        lsp_client.on_request("window/showDocument", |_| Ok(json!({ "success": true })));
+        lsp_client.handle_response().await.unwrap();
     }
 }
@@ -0,0 +1,179 @@
+use crate::protocol::{DocumentChange, Position, ResourceOperation, TextEdit, WorkspaceEdit};
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashMap;
+
+/// Applies a `WorkspaceEdit` to a set of in-memory documents keyed by URI.
+/// Both the `changes` and the `documentChanges` forms are supported; resource operations
+/// create, rename and delete entries of `docs`. Fails if an edit targets a document that
+/// is not in `docs`, in which case `docs` may already be partially edited.
+pub fn apply_workspace_edit(
+    docs: &mut HashMap<String, String>,
+    edit: &WorkspaceEdit,
+) -> Result<()> {
+    if let Some(changes) = &edit.changes {
+        for (uri, edits) in changes {
+            apply_to_document(docs, uri, edits)?;
+        }
+    }
+
+    for change in edit.document_changes.iter().flatten() {
+        match change {
+            DocumentChange::Edit(edit) => {
+                apply_to_document(docs, &edit.text_document.uri, &edit.edits)?
+            }
+            DocumentChange::Operation(ResourceOperation::Create { uri }) => {
+                docs.entry(uri.clone()).or_default();
+            }
+            DocumentChange::Operation(ResourceOperation::Rename { old_uri, new_uri }) => {
+                let text = docs
+                    .remove(old_uri)
+                    .ok_or_else(|| anyhow!("Cannot rename unknown document {}", old_uri))?;
+                docs.insert(new_uri.clone(), text);
+            }
+            DocumentChange::Operation(ResourceOperation::Delete { uri }) => {
+                if docs.remove(uri).is_none() {
+                    bail!("Cannot delete unknown document {}", uri);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn apply_to_document(
+    docs: &mut HashMap<String, String>,
+    uri: &str,
+    edits: &[TextEdit],
+) -> Result<()> {
+    let text = docs
+        .get_mut(uri)
+        .ok_or_else(|| anyhow!("Cannot edit unknown document {}", uri))?;
+    *text = apply_text_edits(text, edits);
+    Ok(())
+}
+
+/// Applies `TextEdit`s, e.g. the result of `textDocument/formatting`, to a single document.
+/// Positions are counted in UTF-16 code units as mandated by LSP. Edits are applied from
+/// the last position in the document to the first, so that earlier offsets stay valid;
+/// edits starting at the same position are inserted in the order given.
+pub fn apply_text_edits(text: &str, edits: &[TextEdit]) -> String {
+    let mut ordered: Vec<(usize, &TextEdit)> = edits.iter().enumerate().collect();
+    ordered.sort_by_key(|(index, edit)| {
+        let start = edit.range.start;
+        std::cmp::Reverse((start.line, start.character, *index))
+    });
+
+    let mut text = text.to_string();
+    for (_, edit) in ordered {
+        let start = offset_at(&text, edit.range.start);
+        let end = offset_at(&text, edit.range.end).max(start);
+        text.replace_range(start..end, &edit.new_text);
+    }
+    text
+}
+
+/// Converts a position, whose character is counted in UTF-16 code units, into a byte
+/// offset. Positions past the end of a line or of the text are clamped to it.
+fn offset_at(text: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match text[line_start..].find('\n') {
+            Some(index) => line_start += index + 1,
+            None => return text.len(),
+        }
+    }
+
+    let line = &text[line_start..];
+    let line = &line[..line.find(['\r', '\n']).unwrap_or(line.len())];
+    let mut units = 0;
+    for (index, c) in line.char_indices() {
+        if units >= position.character as usize {
+            return line_start + index;
+        }
+        units += c.len_utf16();
+    }
+    line_start + line.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{OptionalVersionedTextDocumentIdentifier, Range, TextDocumentEdit};
+    use std::collections::BTreeMap;
+
+    fn edit(start: (u32, u32), end: (u32, u32), new_text: &str) -> TextEdit {
+        TextEdit {
+            range: Range::new(start.0, start.1, end.0, end.1),
+            new_text: new_text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_apply_workspace_edit() {
+        let mut docs = HashMap::from([
+            (
+                "file:///code/main.go".to_string(),
+                "package main\n\nfunc foo() {}\n\nfunc bar() { foo() }\n".to_string(),
+            ),
+            (
+                "file:///code/old.go".to_string(),
+                "package main\n".to_string(),
+            ),
+        ]);
+        let workspace_edit = WorkspaceEdit {
+            changes: None,
+            document_changes: Some(vec![
+                DocumentChange::Edit(TextDocumentEdit {
+                    text_document: OptionalVersionedTextDocumentIdentifier {
+                        uri: "file:///code/main.go".to_string(),
+                        version: Some(1),
+                    },
+                    // Deliberately not in document order
+                    edits: vec![edit((2, 5), (2, 8), "baz"), edit((4, 13), (4, 16), "baz")],
+                }),
+                DocumentChange::Operation(ResourceOperation::Rename {
+                    old_uri: "file:///code/old.go".to_string(),
+                    new_uri: "file:///code/new.go".to_string(),
+                }),
+            ]),
+        };
+
+        apply_workspace_edit(&mut docs, &workspace_edit).unwrap();
+        assert_eq!(
+            docs["file:///code/main.go"],
+            "package main\n\nfunc baz() {}\n\nfunc bar() { baz() }\n"
+        );
+        assert_eq!(docs["file:///code/new.go"], "package main\n");
+        assert!(!docs.contains_key("file:///code/old.go"));
+
+        let unknown = WorkspaceEdit {
+            changes: Some(BTreeMap::from([(
+                "file:///code/missing.go".to_string(),
+                vec![edit((0, 0), (0, 0), "x")],
+            )])),
+            document_changes: None,
+        };
+        assert!(apply_workspace_edit(&mut docs, &unknown).is_err());
+    }
+
+    #[test]
+    fn test_apply_text_edits() {
+        // "é" is one UTF-16 unit, "😀" is two
+        let text = "let s = \"é😀\";\r\nlet  x=1;\n";
+        let edits = vec![
+            edit((1, 4), (1, 5), ""),
+            edit((0, 12), (0, 12), "!"),
+            edit((1, 6), (1, 7), " = "),
+            edit((0, 0), (0, 0), "// a\n"),
+            edit((0, 0), (0, 0), "// b\n"),
+        ];
+        assert_eq!(
+            apply_text_edits(text, &edits),
+            "// a\n// b\nlet s = \"é😀!\";\r\nlet x = 1;\n"
+        );
+
+        // Positions past the end of a line or the document are clamped
+        let edits = vec![edit((0, 40), (0, 40), ";"), edit((9, 0), (9, 0), "\n")];
+        assert_eq!(apply_text_edits("let x = 1", &edits), "let x = 1;\n");
+    }
+}
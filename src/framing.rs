@@ -0,0 +1,364 @@
+use anyhow::{anyhow, bail, Result};
+use std::future::Future;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// How many zero-length reads in a row are retried before the stream is considered at EOF.
+/// Some transports return `Ok(0)` from a read that would otherwise block.
+const ZERO_READ_RETRIES: u32 = 3;
+
+/// Describes how individual messages are delimited on the wire.
+/// The default is `ContentLength`, which implements the standard LSP base protocol.
+/// `LengthPrefixed` is provided for servers using a binary length prefix instead.
+/// Custom implementations can be passed to `LspClient::with_framing` to talk to
+/// servers that use a nonstandard transport.
+pub trait Framing: Send + Sync {
+    /// Wraps a serialized message body into the bytes that are written to the stream.
+    /// Fails if the framing cannot represent the body, e.g. because it is too long.
+    fn encode(&self, body: &[u8]) -> Result<Vec<u8>>;
+
+    /// Reads exactly one message from the stream and returns its body.
+    fn decode<R: AsyncRead + Unpin + Send>(
+        &self,
+        reader: &mut R,
+    ) -> impl Future<Output = Result<Vec<u8>>> + Send;
+}
+
+/// The standard LSP framing: a `Content-Length` header block terminated by
+/// `\r\n\r\n`, followed by the body.
+#[derive(Debug, Default, Clone)]
+pub struct ContentLength {
+    #[cfg(feature = "compression")]
+    accept_compression: bool,
+    headers: Vec<(String, String)>,
+    header_timeout: Option<Duration>,
+}
+
+impl ContentLength {
+    /// Adds a static header to every message written to the server, e.g. an `Authorization`
+    /// header expected by an authenticating gateway in front of the server.
+    /// Fails if the name or value would break the header block.
+    pub fn with_header(
+        mut self,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<Self> {
+        let (name, value) = (name.into(), value.into());
+        if name.is_empty() || name.contains([':', '\r', '\n']) || value.contains(['\r', '\n']) {
+            bail!("Invalid header '{}: {}'", name, value);
+        }
+        self.headers.push((name, value));
+        Ok(self)
+    }
+
+    /// Fails `decode` if the header block is not complete within `timeout` of its first byte,
+    /// e.g. because the server flushed half a header and stalled. Waiting for the first byte
+    /// is not limited, since an idle server sends nothing.
+    pub fn with_header_timeout(mut self, timeout: Duration) -> Self {
+        self.header_timeout = Some(timeout);
+        self
+    }
+
+    /// Advertises gzip/deflate support to the server with an `Accept-Encoding` header
+    /// and transparently decompresses bodies sent with a matching `Content-Encoding`.
+    /// The `Content-Length` always refers to the bytes on the wire.
+    #[cfg(feature = "compression")]
+    pub fn with_compression(mut self, enabled: bool) -> Self {
+        self.accept_compression = enabled;
+        self
+    }
+}
+
+impl Framing for ContentLength {
+    fn encode(&self, body: &[u8]) -> Result<Vec<u8>> {
+        let mut framed = format!("Content-Length: {}\r\n", body.len()).into_bytes();
+        for (name, value) in &self.headers {
+            framed.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+        }
+        #[cfg(feature = "compression")]
+        if self.accept_compression {
+            framed.extend_from_slice(b"Accept-Encoding: gzip, deflate\r\n");
+        }
+        framed.extend_from_slice(b"\r\n");
+        framed.extend_from_slice(body);
+        Ok(framed)
+    }
+
+    async fn decode<R: AsyncRead + Unpin + Send>(&self, reader: &mut R) -> Result<Vec<u8>> {
+        let mut headers = Vec::new();
+        let mut content_length: Option<usize> = None;
+        let mut content_encoding: Option<String> = None;
+
+        // Read headers
+        let mut byte = [0];
+        read_exact(reader, &mut byte).await?;
+        headers.push(byte[0]);
+        let read_headers = async {
+            while !headers.ends_with(b"\r\n\r\n") {
+                read_exact(reader, &mut byte).await?;
+                headers.push(byte[0]);
+            }
+            Ok::<(), std::io::Error>(())
+        };
+        match self.header_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, read_headers)
+                .await
+                .map_err(|_| anyhow!("Timed out after {:?} reading message headers", timeout))??,
+            None => read_headers.await?,
+        }
+
+        let headers_str = String::from_utf8_lossy(&headers);
+        for line in headers_str.lines() {
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("Content-Length") {
+                    content_length = Some(value.trim().parse()?);
+                } else if name.eq_ignore_ascii_case("Content-Encoding") {
+                    content_encoding = Some(value.trim().to_ascii_lowercase());
+                }
+            }
+        }
+
+        let content_length =
+            content_length.ok_or_else(|| anyhow!("Failed to find Content-Length header"))?;
+        let mut body = vec![0u8; content_length];
+        read_exact(reader, &mut body).await?;
+
+        match content_encoding.as_deref() {
+            None | Some("identity") => Ok(body),
+            #[cfg(feature = "compression")]
+            Some(encoding @ ("gzip" | "deflate")) if self.accept_compression => {
+                decompress(encoding, &body)
+            }
+            Some(encoding) => Err(anyhow!("Unsupported Content-Encoding '{}'", encoding)),
+        }
+    }
+}
+
+/// A 4-byte big-endian length prefix followed by the body, for servers that don't
+/// speak the text based header protocol.
+#[derive(Debug, Default, Clone)]
+pub struct LengthPrefixed;
+
+impl Framing for LengthPrefixed {
+    fn encode(&self, body: &[u8]) -> Result<Vec<u8>> {
+        let length = u32::try_from(body.len()).map_err(|_| {
+            anyhow!(
+                "Message body of {} bytes does not fit a 4-byte length prefix",
+                body.len()
+            )
+        })?;
+        let mut framed = length.to_be_bytes().to_vec();
+        framed.extend_from_slice(body);
+        Ok(framed)
+    }
+
+    async fn decode<R: AsyncRead + Unpin + Send>(&self, reader: &mut R) -> Result<Vec<u8>> {
+        let mut length = [0u8; 4];
+        read_exact(reader, &mut length).await?;
+        let mut body = vec![0u8; u32::from_be_bytes(length) as usize];
+        read_exact(reader, &mut body).await?;
+        Ok(body)
+    }
+}
+
+/// Like `AsyncReadExt::read_exact`, but tolerates up to `ZERO_READ_RETRIES` zero-length
+/// reads in a row before failing with `UnexpectedEof`, so a spurious `Ok(0)` is not
+/// mistaken for the end of the stream.
+async fn read_exact<R: AsyncRead + Unpin>(reader: &mut R, buf: &mut [u8]) -> std::io::Result<()> {
+    let mut filled = 0;
+    let mut zero_reads = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]).await? {
+            0 if zero_reads < ZERO_READ_RETRIES => {
+                zero_reads += 1;
+                tokio::task::yield_now().await;
+            }
+            0 => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            n => {
+                filled += n;
+                zero_reads = 0;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "compression")]
+fn decompress(encoding: &str, body: &[u8]) -> Result<Vec<u8>> {
+    use std::io::Read;
+
+    let mut decompressed = Vec::new();
+    if encoding == "gzip" {
+        flate2::read::GzDecoder::new(body).read_to_end(&mut decompressed)?;
+    } else {
+        flate2::read::ZlibDecoder::new(body).read_to_end(&mut decompressed)?;
+    }
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+    use tokio_test::io::Builder;
+
+    #[test]
+    fn test_content_length_encode() {
+        let framed = ContentLength::default().encode(b"{}").unwrap();
+        assert_eq!(framed, b"Content-Length: 2\r\n\r\n{}");
+    }
+
+    #[test]
+    fn test_content_length_extra_headers() {
+        let framing = ContentLength::default()
+            .with_header("Authorization", "Bearer secret")
+            .unwrap();
+        assert_eq!(
+            framing.encode(b"{}").unwrap(),
+            b"Content-Length: 2\r\nAuthorization: Bearer secret\r\n\r\n{}"
+        );
+
+        assert!(ContentLength::default()
+            .with_header("Authorization", "Bearer secret\r\n\r\n{}")
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_content_length_decode() {
+        let mut reader = Builder::new()
+            .read(b"Content-Length: 2\r\nContent-Type: application/vscode-jsonrpc\r\n\r\n{}")
+            .build();
+
+        let body = ContentLength::default().decode(&mut reader).await.unwrap();
+        assert_eq!(body, b"{}");
+    }
+
+    #[tokio::test]
+    async fn test_content_length_decode_chunking() {
+        let message: &[u8] = b"Content-Length: 7\r\n\r\n{\"a\":1}";
+        let framing = ContentLength::default();
+
+        // Two messages, each delivered with its header in a single read
+        let mut reader = Builder::new().read(message).read(message).build();
+        assert_eq!(framing.decode(&mut reader).await.unwrap(), b"{\"a\":1}");
+        assert_eq!(framing.decode(&mut reader).await.unwrap(), b"{\"a\":1}");
+
+        // The header split mid-name and mid-terminator, the body across three reads
+        let mut reader = Builder::new()
+            .read(b"Content-Le")
+            .read(b"ngth: 7\r\n\r")
+            .read(b"\n{\"")
+            .read(b"a\"")
+            .read(b":1}")
+            .build();
+        assert_eq!(framing.decode(&mut reader).await.unwrap(), b"{\"a\":1}");
+    }
+
+    #[tokio::test]
+    async fn test_content_length_header_timeout() {
+        let framing = ContentLength::default().with_header_timeout(Duration::from_millis(50));
+        let (mut server, mut client) = tokio::io::duplex(64);
+
+        // A complete message is unaffected
+        server
+            .write_all(b"Content-Length: 2\r\n\r\n{}")
+            .await
+            .unwrap();
+        assert_eq!(framing.decode(&mut client).await.unwrap(), b"{}");
+
+        // Half a header, with the connection kept open
+        server.write_all(b"Content-Length: 2\r\n").await.unwrap();
+        let err = framing.decode(&mut client).await.unwrap_err();
+        assert!(err.to_string().contains("Timed out"));
+    }
+
+    /// Yields the given chunks one read at a time; an empty chunk is a zero-length read.
+    /// Once all chunks are read, every read returns zero bytes, i.e. EOF.
+    struct ChunkedReader(std::collections::VecDeque<&'static [u8]>);
+
+    impl AsyncRead for ChunkedReader {
+        fn poll_read(
+            mut self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+            buf: &mut tokio::io::ReadBuf<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            if let Some(chunk) = self.0.pop_front() {
+                let n = chunk.len().min(buf.remaining());
+                buf.put_slice(&chunk[..n]);
+                if n < chunk.len() {
+                    self.0.push_front(&chunk[n..]);
+                }
+            }
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_content_length_zero_length_reads() {
+        let framing = ContentLength::default();
+        let mut reader = ChunkedReader(
+            [
+                &b""[..],
+                b"Content-Length: 7\r\n",
+                b"",
+                b"",
+                b"\r\n{\"a\"",
+                b"",
+                b":1}",
+            ]
+            .into(),
+        );
+        assert_eq!(framing.decode(&mut reader).await.unwrap(), b"{\"a\":1}");
+
+        // A stream that keeps returning nothing is at EOF
+        let err = framing.decode(&mut reader).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<std::io::Error>().unwrap().kind(),
+            std::io::ErrorKind::UnexpectedEof
+        );
+        let mut reader = ChunkedReader([&b"Content-Length: 7\r\n\r\n{"[..]].into());
+        assert!(framing.decode(&mut reader).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_length_prefixed_round_trip() {
+        let framing = LengthPrefixed;
+        assert_eq!(framing.encode(b"{}").unwrap(), b"\0\0\0\x02{}");
+
+        let mut stream = framing.encode(br#"{"id":1}"#).unwrap();
+        stream.extend(framing.encode(br#"{"id":2}"#).unwrap());
+        let mut reader = Builder::new().read(&stream).build();
+        assert_eq!(framing.decode(&mut reader).await.unwrap(), br#"{"id":1}"#);
+        assert_eq!(framing.decode(&mut reader).await.unwrap(), br#"{"id":2}"#);
+    }
+
+    #[cfg(feature = "compression")]
+    #[tokio::test]
+    async fn test_content_length_gzip() {
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let framing = ContentLength::default().with_compression(true);
+        assert_eq!(
+            framing.encode(b"{}").unwrap(),
+            b"Content-Length: 2\r\nAccept-Encoding: gzip, deflate\r\n\r\n{}"
+        );
+
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder
+            .write_all(br#"{"jsonrpc":"2.0","id":1,"result":{}}"#)
+            .unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut message = format!(
+            "Content-Length: {}\r\nContent-Encoding: gzip\r\n\r\n",
+            compressed.len()
+        )
+        .into_bytes();
+        message.extend_from_slice(&compressed);
+        let mut reader = Builder::new().read(&message).build();
+
+        let body = framing.decode(&mut reader).await.unwrap();
+        assert_eq!(body, br#"{"jsonrpc":"2.0","id":1,"result":{}}"#);
+    }
+}
@@ -1,2 +1,9 @@
 pub mod client;
+pub mod edit;
+pub mod framing;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod prelude;
 pub mod protocol;
+pub mod testing;
+pub mod uri;
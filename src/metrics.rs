@@ -0,0 +1,209 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+/// Per-method request counts and latencies recorded by the client.
+#[derive(Debug, Default)]
+pub(crate) struct Metrics {
+    methods: HashMap<String, MethodStats>,
+}
+
+#[derive(Debug, Default)]
+struct MethodStats {
+    requests: u64,
+    responses: u64,
+    latencies: Histogram,
+    bytes_sent: u64,
+    bytes_received: u64,
+    largest_received: u64,
+}
+
+impl Metrics {
+    pub(crate) fn record_request(&mut self, method: &str) {
+        self.methods.entry(method.to_string()).or_default().requests += 1;
+    }
+
+    pub(crate) fn record_response(&mut self, method: &str, latency: Duration) {
+        let stats = self.methods.entry(method.to_string()).or_default();
+        stats.responses += 1;
+        stats.latencies.record(latency);
+    }
+
+    /// Records the body size of a message sent for the method.
+    pub(crate) fn record_bytes_sent(&mut self, method: &str, bytes: usize) {
+        self.methods
+            .entry(method.to_string())
+            .or_default()
+            .bytes_sent += bytes as u64;
+    }
+
+    /// Records the body size of a message received for the method, i.e. a response
+    /// to a request with that method or a notification or request from the server.
+    pub(crate) fn record_bytes_received(&mut self, method: &str, bytes: usize) {
+        let stats = self.methods.entry(method.to_string()).or_default();
+        stats.bytes_received += bytes as u64;
+        stats.largest_received = stats.largest_received.max(bytes as u64);
+    }
+
+    pub(crate) fn snapshot(&self) -> MetricsSnapshot {
+        let methods = self
+            .methods
+            .iter()
+            .map(|(method, stats)| {
+                let metrics = MethodMetrics {
+                    requests: stats.requests,
+                    responses: stats.responses,
+                    p50: stats.latencies.percentile(50),
+                    p95: stats.latencies.percentile(95),
+                    p99: stats.latencies.percentile(99),
+                    bytes_sent: stats.bytes_sent,
+                    bytes_received: stats.bytes_received,
+                    largest_received: stats.largest_received,
+                };
+                (method.clone(), metrics)
+            })
+            .collect();
+
+        MetricsSnapshot { methods }
+    }
+}
+
+/// A point-in-time copy of the metrics recorded by the client, keyed by method.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetricsSnapshot {
+    pub methods: BTreeMap<String, MethodMetrics>,
+}
+
+/// Counts, latency percentiles and message sizes for a single method.
+/// Percentiles are `None` until at least one response has been received. They are read
+/// from a log-scale histogram, so they may overstate the actual latency by up to 12.5%.
+/// Sizes are those of the JSON bodies, i.e. without framing headers and after
+/// decompression, so they don't depend on the transport.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MethodMetrics {
+    pub requests: u64,
+    pub responses: u64,
+    pub p50: Option<Duration>,
+    pub p95: Option<Duration>,
+    pub p99: Option<Duration>,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    /// The largest single message received, e.g. to spot bloated responses.
+    pub largest_received: u64,
+}
+
+/// Latencies under `SUB_BUCKETS` microseconds get a bucket each, every power of two above
+/// is split into `SUB_BUCKETS` buckets, i.e. a bucket is at most 12.5% wide.
+const SUB_BUCKET_BITS: u32 = 3;
+const SUB_BUCKETS: u64 = 1 << SUB_BUCKET_BITS;
+/// Latencies above 2^36 microseconds, about 19 hours, are counted in the last bucket.
+const MAX_EXPONENT: u32 = 35;
+const BUCKETS: usize = ((MAX_EXPONENT - SUB_BUCKET_BITS + 2) as u64 * SUB_BUCKETS) as usize;
+
+/// Latency counts in fixed log-scale buckets, so memory does not grow with the session.
+#[derive(Debug)]
+struct Histogram {
+    counts: [u64; BUCKETS],
+    count: u64,
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            counts: [0; BUCKETS],
+            count: 0,
+            max: 0,
+        }
+    }
+}
+
+impl Histogram {
+    fn record(&mut self, latency: Duration) {
+        let micros = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+        self.counts[bucket(micros)] += 1;
+        self.count += 1;
+        self.max = self.max.max(micros);
+    }
+
+    /// Nearest-rank percentile, reported as the upper bound of the bucket it falls in.
+    fn percentile(&self, pct: u64) -> Option<Duration> {
+        let rank = (pct * self.count).div_ceil(100).max(1);
+        let mut seen = 0;
+        self.counts.iter().enumerate().find_map(|(index, count)| {
+            seen += count;
+            (seen >= rank).then(|| Duration::from_micros(upper_bound(index).min(self.max)))
+        })
+    }
+}
+
+fn bucket(micros: u64) -> usize {
+    if micros < SUB_BUCKETS {
+        return micros as usize;
+    }
+    let micros = micros.min((1 << (MAX_EXPONENT + 1)) - 1);
+    let exponent = micros.ilog2();
+    let mantissa = micros >> (exponent - SUB_BUCKET_BITS);
+    ((exponent - SUB_BUCKET_BITS + 1) as u64 * SUB_BUCKETS + mantissa - SUB_BUCKETS) as usize
+}
+
+/// The largest latency, in microseconds, recorded in the bucket.
+fn upper_bound(index: usize) -> u64 {
+    let index = index as u64;
+    if index < 2 * SUB_BUCKETS {
+        return index;
+    }
+    let exponent = (index / SUB_BUCKETS) as u32 + SUB_BUCKET_BITS - 1;
+    let mantissa = index % SUB_BUCKETS + SUB_BUCKETS;
+    ((mantissa + 1) << (exponent - SUB_BUCKET_BITS)) - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_percentiles() {
+        let mut metrics = Metrics::default();
+        for ms in 1..=100 {
+            metrics.record_request("textDocument/hover");
+            metrics.record_response("textDocument/hover", Duration::from_millis(ms));
+        }
+        metrics.record_request("shutdown");
+
+        metrics.record_request("initialize");
+        metrics.record_response("initialize", Duration::from_millis(1234));
+
+        let snapshot = metrics.snapshot();
+        let hover = &snapshot.methods["textDocument/hover"];
+        assert_eq!(hover.requests, 100);
+        assert_eq!(hover.responses, 100);
+        for (percentile, ms) in [(hover.p50, 50), (hover.p95, 95), (hover.p99, 99)] {
+            let actual = Duration::from_millis(ms);
+            let percentile = percentile.unwrap();
+            assert!(percentile >= actual && percentile <= actual * 9 / 8);
+        }
+
+        // A single latency is reported exactly, since percentiles never exceed the maximum
+        let initialize = &snapshot.methods["initialize"];
+        assert_eq!(initialize.p99, Some(Duration::from_millis(1234)));
+
+        let shutdown = &snapshot.methods["shutdown"];
+        assert_eq!(shutdown.requests, 1);
+        assert_eq!(shutdown.p50, None);
+    }
+
+    #[test]
+    fn test_snapshot_message_sizes() {
+        let mut metrics = Metrics::default();
+        metrics.record_bytes_sent("textDocument/semanticTokens/full", 120);
+        metrics.record_bytes_received("textDocument/semanticTokens/full", 2_000_000);
+        metrics.record_bytes_sent("textDocument/semanticTokens/full", 120);
+        metrics.record_bytes_received("textDocument/semanticTokens/full", 500);
+
+        let snapshot = metrics.snapshot();
+        let tokens = &snapshot.methods["textDocument/semanticTokens/full"];
+        assert_eq!(tokens.bytes_sent, 240);
+        assert_eq!(tokens.bytes_received, 2_000_500);
+        assert_eq!(tokens.largest_received, 2_000_000);
+    }
+}
@@ -0,0 +1,15 @@
+//! Re-exports the types most consumers need, for use with a single glob import:
+//!
+//! ```
+//! use lsp_client_rs::prelude::*;
+//! ```
+
+pub use crate::client::{AsyncReadWrite, InitializeOptions, LspClient};
+pub use crate::framing::{ContentLength, Framing};
+pub use crate::protocol::{
+    ClientCapabilities, CompletionContext, CompletionList, CompletionTriggerKind, Diagnostic,
+    DocumentDiagnosticReport, Documentation, GotoResponse, HoverResult, InitializeResult, Location,
+    LocationLink, NotificationMessage, Position, PositionEncoding, ProgressToken, Range, RequestId,
+    RequestMessage, ResponseError, ResponseMessage, ServerCapabilities, ServerNotification,
+    ServerRequest, SignatureHelp, TextDocumentSyncKind, TextEdit, WorkspaceEdit, WorkspaceFolder,
+};
@@ -1,46 +1,513 @@
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BaseMessage {
     pub jsonrpc: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RequestMessage {
     #[serde(flatten)]
     pub base_message: BaseMessage,
     pub id: serde_json::Value,
+    #[serde(default)]
     pub notification: u8,
     pub method: String,
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
     pub params: serde_json::Value,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ResponseMessage {
     #[serde(flatten)]
     pub base_message: BaseMessage,
     pub id: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub result: Option<serde_json::Value>,
-    pub error: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<ResponseError>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+/// The id of a request, which JSON-RPC allows to be either a number or a string.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(untagged)]
+pub enum RequestId {
+    Number(i64),
+    String(String),
+}
+
+impl RequestId {
+    /// Extracts a request id from a raw JSON `id` field.
+    /// Returns `None` for `null` or any other value that is not a valid id.
+    pub fn from_value(value: &serde_json::Value) -> Option<Self> {
+        serde_json::from_value(value.clone()).ok()
+    }
+}
+
+impl std::fmt::Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestId::Number(id) => write!(f, "{}", id),
+            RequestId::String(id) => write!(f, "{:?}", id),
+        }
+    }
+}
+
+/// The error object carried by a response when a request failed.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResponseError {
+    pub code: LspErrorCode,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+impl ResponseError {
+    pub fn new(code: LspErrorCode, message: impl Into<String>) -> Self {
+        ResponseError {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
+impl std::fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} ({}): {}",
+            self.code,
+            i64::from(self.code),
+            self.message
+        )
+    }
+}
+
+impl std::error::Error for ResponseError {}
+
+/// Error codes defined by JSON-RPC and the LSP specification.
+/// Codes that are not known to this crate are preserved in `Other`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(from = "i64", into = "i64")]
+pub enum LspErrorCode {
+    ParseError,
+    InvalidRequest,
+    MethodNotFound,
+    InvalidParams,
+    InternalError,
+    ServerNotInitialized,
+    UnknownErrorCode,
+    RequestFailed,
+    ServerCancelled,
+    ContentModified,
+    RequestCancelled,
+    Other(i64),
+}
+
+impl From<i64> for LspErrorCode {
+    fn from(code: i64) -> Self {
+        match code {
+            -32700 => LspErrorCode::ParseError,
+            -32600 => LspErrorCode::InvalidRequest,
+            -32601 => LspErrorCode::MethodNotFound,
+            -32602 => LspErrorCode::InvalidParams,
+            -32603 => LspErrorCode::InternalError,
+            -32002 => LspErrorCode::ServerNotInitialized,
+            -32001 => LspErrorCode::UnknownErrorCode,
+            -32803 => LspErrorCode::RequestFailed,
+            -32802 => LspErrorCode::ServerCancelled,
+            -32801 => LspErrorCode::ContentModified,
+            -32800 => LspErrorCode::RequestCancelled,
+            other => LspErrorCode::Other(other),
+        }
+    }
+}
+
+impl From<LspErrorCode> for i64 {
+    fn from(code: LspErrorCode) -> Self {
+        match code {
+            LspErrorCode::ParseError => -32700,
+            LspErrorCode::InvalidRequest => -32600,
+            LspErrorCode::MethodNotFound => -32601,
+            LspErrorCode::InvalidParams => -32602,
+            LspErrorCode::InternalError => -32603,
+            LspErrorCode::ServerNotInitialized => -32002,
+            LspErrorCode::UnknownErrorCode => -32001,
+            LspErrorCode::RequestFailed => -32803,
+            LspErrorCode::ServerCancelled => -32802,
+            LspErrorCode::ContentModified => -32801,
+            LspErrorCode::RequestCancelled => -32800,
+            LspErrorCode::Other(other) => other,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct NotificationMessage {
     #[serde(flatten)]
     pub base_message: BaseMessage,
     pub method: String,
+    #[serde(default, skip_serializing_if = "serde_json::Value::is_null")]
     pub params: serde_json::Value,
 }
 
+/// A notification sent by the server, decoded by method where the crate knows its shape.
+#[derive(Debug, Clone)]
+pub enum ServerNotification {
+    TelemetryEvent(TelemetryEventParams),
+    Progress(ProgressParams),
+    CancelRequest(CancelParams),
+    PublishDiagnostics(PublishDiagnosticsParams),
+    Other(NotificationMessage),
+}
+
+impl From<NotificationMessage> for ServerNotification {
+    fn from(notification: NotificationMessage) -> Self {
+        match notification.method.as_str() {
+            "telemetry/event" => {
+                ServerNotification::TelemetryEvent(TelemetryEventParams(notification.params))
+            }
+            "$/progress" => match serde_json::from_value(notification.params.clone()) {
+                Ok(params) => ServerNotification::Progress(params),
+                Err(_) => ServerNotification::Other(notification),
+            },
+            "$/cancelRequest" => match serde_json::from_value(notification.params.clone()) {
+                Ok(params) => ServerNotification::CancelRequest(params),
+                Err(_) => ServerNotification::Other(notification),
+            },
+            "textDocument/publishDiagnostics" => {
+                match serde_json::from_value(notification.params.clone()) {
+                    Ok(params) => ServerNotification::PublishDiagnostics(params),
+                    Err(_) => ServerNotification::Other(notification),
+                }
+            }
+            _ => ServerNotification::Other(notification),
+        }
+    }
+}
+
+/// The params of a `$/cancelRequest` notification.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CancelParams {
+    pub id: RequestId,
+}
+
+/// The params of a `textDocument/publishDiagnostics` notification.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PublishDiagnosticsParams {
+    pub uri: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<i32>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// A compiler error, warning, etc. reported for a range of a document.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub range: Range,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severity: Option<DiagnosticSeverity>,
+    /// A number or string, depending on the server.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<serde_json::Value>,
+    #[serde(
+        rename = "codeDescription",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub code_description: Option<CodeDescription>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<DiagnosticTag>>,
+    #[serde(
+        rename = "relatedInformation",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub related_information: Option<Vec<DiagnosticRelatedInformation>>,
+}
+
+/// Links a diagnostic code to its documentation.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CodeDescription {
+    pub href: String,
+}
+
+/// Another location related to a diagnostic, e.g. the first of two duplicate definitions.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DiagnosticRelatedInformation {
+    pub location: Location,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Groups diagnostics by severity, from errors to hints. Diagnostics without a severity
+    /// are counted as errors, which is how most editors display them.
+    pub fn group_by_severity(
+        diagnostics: &[Diagnostic],
+    ) -> BTreeMap<DiagnosticSeverity, Vec<&Diagnostic>> {
+        let mut groups: BTreeMap<DiagnosticSeverity, Vec<&Diagnostic>> = BTreeMap::new();
+        for diagnostic in diagnostics {
+            let severity = diagnostic.severity.unwrap_or(DiagnosticSeverity::Error);
+            groups.entry(severity).or_default().push(diagnostic);
+        }
+        groups
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum DiagnosticSeverity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl TryFrom<u8> for DiagnosticSeverity {
+    type Error = String;
+
+    fn try_from(severity: u8) -> std::result::Result<Self, String> {
+        match severity {
+            1 => Ok(DiagnosticSeverity::Error),
+            2 => Ok(DiagnosticSeverity::Warning),
+            3 => Ok(DiagnosticSeverity::Information),
+            4 => Ok(DiagnosticSeverity::Hint),
+            other => Err(format!("Invalid diagnostic severity {}", other)),
+        }
+    }
+}
+
+impl From<DiagnosticSeverity> for u8 {
+    fn from(severity: DiagnosticSeverity) -> Self {
+        match severity {
+            DiagnosticSeverity::Error => 1,
+            DiagnosticSeverity::Warning => 2,
+            DiagnosticSeverity::Information => 3,
+            DiagnosticSeverity::Hint => 4,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum DiagnosticTag {
+    /// Unused or unnecessary code, usually rendered faded out.
+    Unnecessary,
+    /// Deprecated or obsolete code, usually rendered struck through.
+    Deprecated,
+}
+
+impl TryFrom<u8> for DiagnosticTag {
+    type Error = String;
+
+    fn try_from(tag: u8) -> std::result::Result<Self, String> {
+        match tag {
+            1 => Ok(DiagnosticTag::Unnecessary),
+            2 => Ok(DiagnosticTag::Deprecated),
+            other => Err(format!("Invalid diagnostic tag {}", other)),
+        }
+    }
+}
+
+impl From<DiagnosticTag> for u8 {
+    fn from(tag: DiagnosticTag) -> Self {
+        match tag {
+            DiagnosticTag::Unnecessary => 1,
+            DiagnosticTag::Deprecated => 2,
+        }
+    }
+}
+
+/// The result of a `textDocument/diagnostic` request. `Unchanged` is returned when the
+/// diagnostics are the same as for the `previousResultId` sent with the request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum DocumentDiagnosticReport {
+    Full {
+        #[serde(rename = "resultId", default, skip_serializing_if = "Option::is_none")]
+        result_id: Option<String>,
+        items: Vec<Diagnostic>,
+    },
+    Unchanged {
+        #[serde(rename = "resultId")]
+        result_id: String,
+    },
+}
+
+/// A `resultId` previously reported for a document, sent with `workspace/diagnostic`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PreviousResultId {
+    pub uri: String,
+    pub value: String,
+}
+
+/// The result of a `workspace/diagnostic` request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WorkspaceDiagnosticReport {
+    pub items: Vec<WorkspaceDocumentDiagnosticReport>,
+}
+
+/// The diagnostic report for a single document of a `workspace/diagnostic` result.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WorkspaceDocumentDiagnosticReport {
+    pub uri: String,
+    /// The version of the document the diagnostics were computed for, `None` if unknown.
+    pub version: Option<i32>,
+    #[serde(flatten)]
+    pub report: DocumentDiagnosticReport,
+}
+
+/// A request sent by the server, decoded by method where the crate knows its shape.
+#[derive(Debug, Clone)]
+pub enum ServerRequest {
+    SemanticTokensRefresh,
+    CodeLensRefresh,
+    InlayHintRefresh,
+    /// The server asks the client to re-pull diagnostics with `textDocument/diagnostic`
+    /// or `workspace/diagnostic`.
+    DiagnosticRefresh,
+    WorkDoneProgressCreate(WorkDoneProgressCreateParams),
+    /// The server asks the client to show a document or an external URL.
+    ShowDocument(ShowDocumentParams),
+    Other(RequestMessage),
+}
+
+impl From<RequestMessage> for ServerRequest {
+    fn from(request: RequestMessage) -> Self {
+        match request.method.as_str() {
+            "workspace/semanticTokens/refresh" => ServerRequest::SemanticTokensRefresh,
+            "workspace/codeLens/refresh" => ServerRequest::CodeLensRefresh,
+            "workspace/inlayHint/refresh" => ServerRequest::InlayHintRefresh,
+            "workspace/diagnostic/refresh" => ServerRequest::DiagnosticRefresh,
+            "window/workDoneProgress/create" => {
+                match serde_json::from_value(request.params.clone()) {
+                    Ok(params) => ServerRequest::WorkDoneProgressCreate(params),
+                    Err(_) => ServerRequest::Other(request),
+                }
+            }
+            "window/showDocument" => match serde_json::from_value(request.params.clone()) {
+                Ok(params) => ServerRequest::ShowDocument(params),
+                Err(_) => ServerRequest::Other(request),
+            },
+            _ => ServerRequest::Other(request),
+        }
+    }
+}
+
+/// A token identifying a stream of progress or partial result notifications.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(untagged)]
+pub enum ProgressToken {
+    Number(i64),
+    String(String),
+}
+
+/// The params of a `window/workDoneProgress/create` request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WorkDoneProgressCreateParams {
+    pub token: ProgressToken,
+}
+
+/// The params of a `window/showDocument` request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ShowDocumentParams {
+    pub uri: String,
+    /// Whether to show the URI in an external program, e.g. a browser.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub take_focus: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub selection: Option<Range>,
+}
+
+/// The result of a `window/showDocument` request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ShowDocumentResult {
+    pub success: bool,
+}
+
+/// The params of a `$/progress` notification.
+/// For partial results, `value` holds a chunk of the final result.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProgressParams {
+    pub token: ProgressToken,
+    pub value: serde_json::Value,
+}
+
+impl ProgressParams {
+    /// Decodes `value` as work done progress. Returns `None` for other progress,
+    /// e.g. partial results.
+    pub fn work_done(&self) -> Option<WorkDoneProgress> {
+        serde_json::from_value(self.value.clone()).ok()
+    }
+}
+
+/// The `value` of a `$/progress` notification reporting work done progress.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum WorkDoneProgress {
+    Begin {
+        title: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cancellable: Option<bool>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+        /// From 0 to 100. Progress without a percentage is shown as indeterminate.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        percentage: Option<u32>,
+    },
+    Report {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cancellable: Option<bool>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        percentage: Option<u32>,
+    },
+    End {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+    },
+}
+
+impl WorkDoneProgress {
+    pub fn percentage(&self) -> Option<u32> {
+        match self {
+            WorkDoneProgress::Begin { percentage, .. }
+            | WorkDoneProgress::Report { percentage, .. } => *percentage,
+            WorkDoneProgress::End { .. } => None,
+        }
+    }
+
+    pub fn message(&self) -> Option<&str> {
+        match self {
+            WorkDoneProgress::Begin { message, .. }
+            | WorkDoneProgress::Report { message, .. }
+            | WorkDoneProgress::End { message } => message.as_deref(),
+        }
+    }
+}
+
+/// The payload of a `telemetry/event` notification, which is arbitrary JSON.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TelemetryEventParams(pub serde_json::Value);
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct InitializeParams {
     #[serde(rename = "processId")]
-    pub process_id: u32,
+    pub process_id: Option<u32>,
     #[serde(rename = "rootUri")]
     pub root_uri: String,
-    #[serde(rename = "clientInfo")]
-    pub client_info: ClientInfo,
+    #[serde(rename = "clientInfo", skip_serializing_if = "Option::is_none")]
+    pub client_info: Option<ClientInfo>,
     pub capabilities: ClientCapabilities, // Direct embedding
     #[serde(rename = "workspaceFolders")]
     pub workspace_folders: Option<Vec<WorkspaceFolder>>,
@@ -49,7 +516,8 @@ pub struct InitializeParams {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ClientInfo {
     pub name: String,
-    pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -58,11 +526,314 @@ pub struct WorkspaceFolder {
     pub name: String,
 }
 
+impl WorkspaceFolder {
+    /// Creates a workspace folder, failing if `uri` is not well-formed, see `uri::validate`.
+    pub fn new(uri: impl Into<String>, name: impl Into<String>) -> Result<Self> {
+        let uri = uri.into();
+        crate::uri::validate(&uri)?;
+        Ok(WorkspaceFolder {
+            uri,
+            name: name.into(),
+        })
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ClientCapabilities {
     pub workspace: Option<CapabilitiesWorkspace>, // Changed from HashMap to direct struct
     #[serde(rename = "textDocument")]
     pub text_document: Option<CapabilitiesTextDocument>, // Changed from HashMap to direct struct
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub general: Option<CapabilitiesGeneral>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window: Option<CapabilitiesWindow>,
+}
+
+/// The capabilities advertised by `new_initialize`. Start from `ClientCapabilities::default()`
+/// and chain the `with_*` methods to advertise more.
+impl Default for ClientCapabilities {
+    fn default() -> Self {
+        ClientCapabilities {
+            workspace: Some(CapabilitiesWorkspace::default()),
+            text_document: Some(CapabilitiesTextDocument::default()),
+            general: None,
+            window: Some(CapabilitiesWindow::default()),
+        }
+    }
+}
+
+impl ClientCapabilities {
+    /// Advertises whether the client supports server initiated progress, i.e.
+    /// `window/workDoneProgress/create` followed by `$/progress`. On by default.
+    pub fn with_work_done_progress(mut self, enabled: bool) -> Self {
+        self.window
+            .get_or_insert_with(CapabilitiesWindow::default)
+            .work_done_progress = enabled;
+        self
+    }
+
+    /// Advertises support for `window/showMessageRequest`.
+    /// additional_properties_support - Whether action items may carry extra properties,
+    /// which the client sends back with the chosen item.
+    pub fn with_show_message(mut self, additional_properties_support: bool) -> Self {
+        self.window
+            .get_or_insert_with(CapabilitiesWindow::default)
+            .show_message = Some(ShowMessageRequestCapability {
+            message_action_item: Some(MessageActionItemCapability {
+                additional_properties_support,
+            }),
+        });
+        self
+    }
+
+    /// Advertises support for `window/showDocument`.
+    pub fn with_show_document(mut self, support: bool) -> Self {
+        self.window
+            .get_or_insert_with(CapabilitiesWindow::default)
+            .show_document = Some(ShowDocumentCapability { support });
+        self
+    }
+
+    /// Advertises the markdown parser used to render server provided documentation,
+    /// e.g. `marked` version `1.1.0` for GitHub flavored markdown.
+    pub fn with_markdown(mut self, parser: impl Into<String>, version: Option<String>) -> Self {
+        self.general
+            .get_or_insert_with(CapabilitiesGeneral::default)
+            .markdown = Some(Markdown {
+            parser: parser.into(),
+            version,
+            allowed_tags: None,
+        });
+        self
+    }
+
+    /// Advertises the position encodings the client supports, in order of preference,
+    /// e.g. `["utf-8", "utf-16"]`. Servers fall back to `utf-16` when not advertised.
+    pub fn with_position_encodings(mut self, encodings: Vec<String>) -> Self {
+        self.general
+            .get_or_insert_with(CapabilitiesGeneral::default)
+            .position_encodings = Some(encodings);
+        self
+    }
+
+    /// Advertises how the client handles requests that became stale because the
+    /// document changed.
+    /// cancel - Whether the client cancels such requests itself.
+    /// retry_on_content_modified - Methods the client retries when answered with `ContentModified`.
+    pub fn with_stale_request_support(
+        mut self,
+        cancel: bool,
+        retry_on_content_modified: Vec<String>,
+    ) -> Self {
+        self.general
+            .get_or_insert_with(CapabilitiesGeneral::default)
+            .stale_request_support = Some(StaleRequestSupport {
+            cancel,
+            retry_on_content_modified,
+        });
+        self
+    }
+
+    /// Advertises the regular expression engine used for patterns sent by the server,
+    /// e.g. `ECMAScript` version `ES2020`.
+    pub fn with_regular_expressions(
+        mut self,
+        engine: impl Into<String>,
+        version: Option<String>,
+    ) -> Self {
+        self.general
+            .get_or_insert_with(CapabilitiesGeneral::default)
+            .regular_expressions = Some(RegularExpressions {
+            engine: engine.into(),
+            version,
+        });
+        self
+    }
+
+    /// Sets the formats the client accepts for hover contents, in order of preference,
+    /// e.g. `["markdown", "plaintext"]`. Only `plaintext` is advertised by default.
+    pub fn with_hover_content_format(mut self, content_format: Vec<String>) -> Self {
+        self.text_document
+            .get_or_insert_with(CapabilitiesTextDocument::default)
+            .hover
+            .content_format = content_format;
+        self
+    }
+
+    /// Advertises semantic tokens support, which servers may otherwise turn off.
+    /// Not advertised by default.
+    pub fn with_semantic_tokens(mut self, semantic_tokens: SemanticTokensCapability) -> Self {
+        self.text_document
+            .get_or_insert_with(CapabilitiesTextDocument::default)
+            .semantic_tokens = Some(semantic_tokens);
+        self
+    }
+
+    /// Sets the save notifications and requests the client advertises, e.g.
+    /// `willSaveWaitUntil` to have the server format documents on save.
+    pub fn with_synchronization(mut self, synchronization: SynchronizationCapability) -> Self {
+        self.text_document
+            .get_or_insert_with(CapabilitiesTextDocument::default)
+            .synchronization = Some(synchronization);
+        self
+    }
+
+    /// Sets whether the client asks for the nested `DocumentSymbol[]` result of
+    /// `textDocument/documentSymbol`. On by default.
+    pub fn with_hierarchical_document_symbol_support(mut self, enabled: bool) -> Self {
+        self.text_document
+            .get_or_insert_with(CapabilitiesTextDocument::default)
+            .document_symbol = Some(DocumentSymbolCapability {
+            hierarchical_document_symbol_support: enabled,
+        });
+        self
+    }
+
+    /// Sets the code action properties the server may leave out and only fill in when the
+    /// action is resolved. Only `edit` is advertised by default; an empty list turns off
+    /// code action resolution.
+    pub fn with_code_action_resolve_support(mut self, properties: Vec<String>) -> Self {
+        let code_action = &mut self
+            .text_document
+            .get_or_insert_with(CapabilitiesTextDocument::default)
+            .code_action;
+        if properties.is_empty() {
+            code_action.data_support = None;
+            code_action.resolve_support = None;
+        } else {
+            code_action.data_support = Some(true);
+            code_action.resolve_support = Some(ResolveSupport { properties });
+        }
+        self
+    }
+
+    /// Advertises which completion item properties the server may leave out and only fill
+    /// in when the item is resolved, e.g. `["documentation", "detail"]`.
+    pub fn with_completion_resolve_support(mut self, properties: Vec<String>) -> Self {
+        self.text_document
+            .get_or_insert_with(CapabilitiesTextDocument::default)
+            .completion
+            .completion_item
+            .resolve_support = Some(ResolveSupport { properties });
+        self
+    }
+
+    /// Advertises whether the client understands `relatedInformation` in published diagnostics.
+    pub fn with_diagnostic_related_information(mut self, enabled: bool) -> Self {
+        self.text_document
+            .get_or_insert_with(CapabilitiesTextDocument::default)
+            .publish_diagnostics
+            .get_or_insert_with(PublishDiagnosticsCapability::default)
+            .related_information = Some(enabled);
+        self
+    }
+
+    /// Advertises which `DiagnosticTag`s the client understands in published diagnostics.
+    pub fn with_diagnostic_tag_support(mut self, value_set: Vec<DiagnosticTag>) -> Self {
+        self.text_document
+            .get_or_insert_with(CapabilitiesTextDocument::default)
+            .publish_diagnostics
+            .get_or_insert_with(PublishDiagnosticsCapability::default)
+            .tag_support = Some(DiagnosticTagSupport { value_set });
+        self
+    }
+
+    /// Advertises support for the `workspace/willCreateFiles`, `workspace/didRenameFiles`,
+    /// etc. requests and notifications.
+    pub fn with_file_operations(mut self, file_operations: FileOperations) -> Self {
+        self.workspace
+            .get_or_insert_with(CapabilitiesWorkspace::default)
+            .file_operations = Some(file_operations);
+        self
+    }
+
+    /// Advertises which workspace symbol properties the client can resolve lazily with
+    /// `workspaceSymbol/resolve`, e.g. `location.range`.
+    pub fn with_workspace_symbol_resolve_support(mut self, properties: Vec<String>) -> Self {
+        self.workspace
+            .get_or_insert_with(CapabilitiesWorkspace::default)
+            .symbol
+            .get_or_insert_with(WorkspaceSymbolCapability::default)
+            .resolve_support = Some(ResolveSupport { properties });
+        self
+    }
+}
+
+/// The `window` client capabilities.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CapabilitiesWindow {
+    pub work_done_progress: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub show_message: Option<ShowMessageRequestCapability>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub show_document: Option<ShowDocumentCapability>,
+}
+
+impl Default for CapabilitiesWindow {
+    fn default() -> Self {
+        CapabilitiesWindow {
+            work_done_progress: true,
+            show_message: None,
+            show_document: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ShowMessageRequestCapability {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_action_item: Option<MessageActionItemCapability>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageActionItemCapability {
+    pub additional_properties_support: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ShowDocumentCapability {
+    pub support: bool,
+}
+
+/// The `general` client capabilities.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CapabilitiesGeneral {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub markdown: Option<Markdown>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position_encodings: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stale_request_support: Option<StaleRequestSupport>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub regular_expressions: Option<RegularExpressions>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Markdown {
+    pub parser: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_tags: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct StaleRequestSupport {
+    pub cancel: bool,
+    pub retry_on_content_modified: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RegularExpressions {
+    pub engine: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -72,8 +843,50 @@ pub struct CapabilitiesWorkspace {
     #[serde(rename = "didChangeConfiguration")]
     pub did_change_configuration: DidChangeConfiguration,
     #[serde(rename = "workspaceEdit")]
-    pub workspace_edit: WorkspaceEdit,
+    pub workspace_edit: WorkspaceEditCapability,
     pub configuration: bool,
+    #[serde(rename = "fileOperations", skip_serializing_if = "Option::is_none")]
+    pub file_operations: Option<FileOperations>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<WorkspaceSymbolCapability>,
+}
+
+impl Default for CapabilitiesWorkspace {
+    fn default() -> Self {
+        CapabilitiesWorkspace {
+            workspace_folders: true,
+            did_change_configuration: DidChangeConfiguration {
+                dynamic_registration: true,
+            },
+            workspace_edit: WorkspaceEditCapability {
+                document_changes: true,
+            },
+            configuration: true,
+            file_operations: None,
+            symbol: None,
+        }
+    }
+}
+
+/// The `workspace.symbol` client capabilities.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceSymbolCapability {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolve_support: Option<ResolveSupport>,
+}
+
+/// The `workspace.fileOperations` client capabilities.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FileOperations {
+    pub dynamic_registration: bool,
+    pub did_create: bool,
+    pub will_create: bool,
+    pub did_rename: bool,
+    pub will_rename: bool,
+    pub did_delete: bool,
+    pub will_delete: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -83,7 +896,7 @@ pub struct DidChangeConfiguration {
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-pub struct WorkspaceEdit {
+pub struct WorkspaceEditCapability {
     #[serde(rename = "documentChanges")]
     pub document_changes: bool,
 }
@@ -94,6 +907,181 @@ pub struct CapabilitiesTextDocument {
     pub completion: Completion,
     #[serde(rename = "codeAction")]
     pub code_action: CodeAction,
+    #[serde(rename = "publishDiagnostics", skip_serializing_if = "Option::is_none")]
+    pub publish_diagnostics: Option<PublishDiagnosticsCapability>,
+    #[serde(rename = "documentSymbol", skip_serializing_if = "Option::is_none")]
+    pub document_symbol: Option<DocumentSymbolCapability>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub synchronization: Option<SynchronizationCapability>,
+    #[serde(rename = "semanticTokens", skip_serializing_if = "Option::is_none")]
+    pub semantic_tokens: Option<SemanticTokensCapability>,
+}
+
+/// The `textDocument.semanticTokens` client capabilities. The default advertises the full,
+/// delta and range requests with the token types and modifiers predefined by the
+/// specification, in the relative format, without overlapping or multiline tokens.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SemanticTokensCapability {
+    pub requests: SemanticTokensRequests,
+    pub token_types: Vec<String>,
+    pub token_modifiers: Vec<String>,
+    pub formats: Vec<String>,
+    pub overlapping_token_support: bool,
+    /// Without it, servers split tokens that span several lines into one per line.
+    pub multiline_token_support: bool,
+}
+
+/// Which `textDocument/semanticTokens` requests the client sends.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SemanticTokensRequests {
+    pub range: bool,
+    pub full: SemanticTokensFullRequests,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SemanticTokensFullRequests {
+    /// Whether the client sends `textDocument/semanticTokens/full/delta`.
+    pub delta: bool,
+}
+
+impl Default for SemanticTokensCapability {
+    fn default() -> Self {
+        let token_types = [
+            "namespace",
+            "type",
+            "class",
+            "enum",
+            "interface",
+            "struct",
+            "typeParameter",
+            "parameter",
+            "variable",
+            "property",
+            "enumMember",
+            "event",
+            "function",
+            "method",
+            "macro",
+            "keyword",
+            "modifier",
+            "comment",
+            "string",
+            "number",
+            "regexp",
+            "operator",
+            "decorator",
+        ];
+        let token_modifiers = [
+            "declaration",
+            "definition",
+            "readonly",
+            "static",
+            "deprecated",
+            "abstract",
+            "async",
+            "modification",
+            "documentation",
+            "defaultLibrary",
+        ];
+        SemanticTokensCapability {
+            requests: SemanticTokensRequests {
+                range: true,
+                full: SemanticTokensFullRequests { delta: true },
+            },
+            token_types: token_types.map(String::from).to_vec(),
+            token_modifiers: token_modifiers.map(String::from).to_vec(),
+            formats: vec!["relative".to_string()],
+            overlapping_token_support: false,
+            multiline_token_support: false,
+        }
+    }
+}
+
+/// The `textDocument.synchronization` client capabilities, i.e. which save notifications
+/// and requests the client sends. By default `didSave` and `willSave` are advertised.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SynchronizationCapability {
+    pub dynamic_registration: bool,
+    pub will_save: bool,
+    /// Whether the client sends `textDocument/willSaveWaitUntil` and applies the edits
+    /// the server answers with before saving, e.g. for format on save.
+    pub will_save_wait_until: bool,
+    pub did_save: bool,
+}
+
+impl Default for SynchronizationCapability {
+    fn default() -> Self {
+        SynchronizationCapability {
+            dynamic_registration: false,
+            will_save: true,
+            will_save_wait_until: false,
+            did_save: true,
+        }
+    }
+}
+
+/// The `textDocument.documentSymbol` client capabilities.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentSymbolCapability {
+    /// Whether the client understands the nested `DocumentSymbol[]` result.
+    /// Without it, servers answer with the flat `SymbolInformation[]` form.
+    pub hierarchical_document_symbol_support: bool,
+}
+
+/// The `textDocument.publishDiagnostics` client capabilities.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PublishDiagnosticsCapability {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub related_information: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag_support: Option<DiagnosticTagSupport>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticTagSupport {
+    pub value_set: Vec<DiagnosticTag>,
+}
+
+impl Default for CapabilitiesTextDocument {
+    fn default() -> Self {
+        CapabilitiesTextDocument {
+            hover: Hover {
+                content_format: vec!["plaintext".to_string()],
+            },
+            completion: Completion {
+                completion_item: CompletionItemCapability {
+                    snippet_support: true,
+                    resolve_support: None,
+                },
+            },
+            code_action: CodeAction {
+                code_action_literal_support: CodeActionLiteralSupport {
+                    code_action_kind: CodeActionKind {
+                        value_set: vec![
+                            "source.organizeImports".to_string(),
+                            "refactor.rewrite".to_string(),
+                            "refactor.extract".to_string(),
+                        ],
+                    },
+                },
+                data_support: Some(true),
+                resolve_support: Some(ResolveSupport {
+                    properties: vec!["edit".to_string()],
+                }),
+            },
+            publish_diagnostics: None,
+            document_symbol: Some(DocumentSymbolCapability {
+                hierarchical_document_symbol_support: true,
+            }),
+            synchronization: Some(SynchronizationCapability::default()),
+            semantic_tokens: None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -105,19 +1093,36 @@ pub struct Hover {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Completion {
     #[serde(rename = "completionItem")]
-    pub completion_item: CompletionItem,
+    pub completion_item: CompletionItemCapability,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
-pub struct CompletionItem {
+pub struct CompletionItemCapability {
     #[serde(rename = "snippetSupport")]
     pub snippet_support: bool,
+    #[serde(rename = "resolveSupport", skip_serializing_if = "Option::is_none")]
+    pub resolve_support: Option<ResolveSupport>,
+}
+
+/// The properties of a completion item the client can resolve lazily with
+/// `completionItem/resolve`, e.g. `documentation` or `detail`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ResolveSupport {
+    pub properties: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CodeAction {
     #[serde(rename = "codeActionLiteralSupport")]
     pub code_action_literal_support: CodeActionLiteralSupport,
+    /// Whether the client preserves the `data` of a code action between
+    /// `textDocument/codeAction` and `codeAction/resolve`.
+    #[serde(rename = "dataSupport", skip_serializing_if = "Option::is_none")]
+    pub data_support: Option<bool>,
+    /// The code action properties the server may leave out and only fill in on
+    /// `codeAction/resolve`.
+    #[serde(rename = "resolveSupport", skip_serializing_if = "Option::is_none")]
+    pub resolve_support: Option<ResolveSupport>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -132,296 +1137,3926 @@ pub struct CodeActionKind {
     pub value_set: Vec<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Location {
-    uri: String,
-    range: Range,
+/// The result of the `initialize` request.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct InitializeResult {
+    #[serde(default)]
+    pub capabilities: ServerCapabilities,
+    #[serde(rename = "serverInfo", skip_serializing_if = "Option::is_none")]
+    pub server_info: Option<ServerInfo>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Range {
-    start: Position,
-    end: Position,
+/// The capabilities a server announces in its `initialize` result.
+/// Only some are typed; all others are kept as is in `other`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerCapabilities {
+    /// The encoding the server picked from the client's `general.positionEncodings`.
+    /// `None` means UTF-16.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position_encoding: Option<PositionEncoding>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completion_provider: Option<CompletionOptions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature_help_provider: Option<SignatureHelpOptions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text_document_sync: Option<TextDocumentSync>,
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Position {
-    line: u32,
-    character: u32,
-}
+impl ServerCapabilities {
+    /// The characters that trigger completion, empty if the server advertises none.
+    pub fn completion_trigger_characters(&self) -> &[String] {
+        self.completion_provider
+            .as_ref()
+            .and_then(|provider| provider.trigger_characters.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// The characters that accept any completion item, empty if the server advertises none.
+    pub fn completion_commit_characters(&self) -> &[String] {
+        self.completion_provider
+            .as_ref()
+            .and_then(|provider| provider.all_commit_characters.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// The characters that trigger signature help, empty if the server advertises none.
+    pub fn signature_help_trigger_characters(&self) -> &[String] {
+        self.signature_help_provider
+            .as_ref()
+            .and_then(|provider| provider.trigger_characters.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// How the server wants `textDocument/didChange` to be sent, from either form of
+    /// `textDocumentSync`. `None` if the server advertises no change synchronization.
+    pub fn text_document_sync_kind(&self) -> TextDocumentSyncKind {
+        match &self.text_document_sync {
+            Some(TextDocumentSync::Kind(kind)) => *kind,
+            Some(TextDocumentSync::Options(options)) => {
+                options.change.unwrap_or(TextDocumentSyncKind::None)
+            }
+            None => TextDocumentSyncKind::None,
+        }
+    }
+}
+
+/// The `textDocumentSync` server capability, either the sync kind as a number or an
+/// options object.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum TextDocumentSync {
+    Kind(TextDocumentSyncKind),
+    Options(TextDocumentSyncOptions),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TextDocumentSyncOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub open_close: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub change: Option<TextDocumentSyncKind>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub will_save: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub will_save_wait_until: Option<bool>,
+    /// Either a boolean or `{ "includeText": bool }`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub save: Option<serde_json::Value>,
+}
+
+/// How `textDocument/didChange` notifications are sent to the server.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum TextDocumentSyncKind {
+    /// Documents are not synchronized.
+    #[default]
+    None,
+    /// Every change sends the full text of the document.
+    Full,
+    /// Changes are sent as incremental edits.
+    Incremental,
+}
+
+impl TryFrom<u8> for TextDocumentSyncKind {
+    type Error = String;
+
+    fn try_from(kind: u8) -> std::result::Result<Self, String> {
+        match kind {
+            0 => Ok(TextDocumentSyncKind::None),
+            1 => Ok(TextDocumentSyncKind::Full),
+            2 => Ok(TextDocumentSyncKind::Incremental),
+            other => Err(format!("Invalid text document sync kind {}", other)),
+        }
+    }
+}
+
+impl From<TextDocumentSyncKind> for u8 {
+    fn from(kind: TextDocumentSyncKind) -> Self {
+        match kind {
+            TextDocumentSyncKind::None => 0,
+            TextDocumentSyncKind::Full => 1,
+            TextDocumentSyncKind::Incremental => 2,
+        }
+    }
+}
+
+/// The `completionProvider` server capability.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trigger_characters: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub all_commit_characters: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolve_provider: Option<bool>,
+}
+
+/// The `signatureHelpProvider` server capability.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SignatureHelpOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trigger_characters: Option<Vec<String>>,
+    /// Characters that re-trigger signature help while it is already showing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retrigger_characters: Option<Vec<String>>,
+}
+
+/// Information about the server, as reported in the `initialize` result.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ServerInfo {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Location {
+    pub uri: String,
+    pub range: Range,
+}
+
+/// A link from a range in the origin document, usually the symbol the request was made
+/// on, to a range in the target document.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LocationLink {
+    /// The span in the origin document to highlight, if the server sent one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin_selection_range: Option<Range>,
+    pub target_uri: String,
+    /// The full range of the target, e.g. the whole body of a function.
+    pub target_range: Range,
+    /// The part of `target_range` to select, e.g. the name of a function.
+    pub target_selection_range: Range,
+}
+
+/// The result of a goto request like `textDocument/definition`, in whichever of
+/// the forms allowed by the specification the server chose to answer with.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum GotoResponse {
+    Scalar(Location),
+    Array(Vec<Location>),
+    Links(Vec<LocationLink>),
+}
+
+impl GotoResponse {
+    /// Returns the target locations. Links are reduced to their target selection range.
+    pub fn locations(&self) -> Vec<Location> {
+        match self {
+            GotoResponse::Scalar(location) => vec![location.clone()],
+            GotoResponse::Array(locations) => locations.clone(),
+            GotoResponse::Links(links) => links
+                .iter()
+                .map(|link| Location {
+                    uri: link.target_uri.clone(),
+                    range: link.target_selection_range.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
 
 impl Position {
     pub fn new(line: u32, character: u32) -> Self {
         Position { line, character }
     }
+
+    /// Converts a byte offset into `text` to a position, counting the character offset
+    /// in the units of `encoding`. Fails if the offset is past the end of the text or
+    /// not on a character boundary.
+    pub fn from_byte_offset(text: &str, offset: usize, encoding: PositionEncoding) -> Result<Self> {
+        if !text.is_char_boundary(offset) {
+            bail!(
+                "Byte offset {} is not on a character boundary of a {} byte text",
+                offset,
+                text.len()
+            );
+        }
+        let before = &text[..offset];
+        let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+        let line = before.matches('\n').count();
+        let prefix = &before[line_start..];
+        let character = match encoding {
+            PositionEncoding::Utf8 => prefix.len(),
+            PositionEncoding::Utf16 => prefix.encode_utf16().count(),
+            PositionEncoding::Utf32 => prefix.chars().count(),
+        };
+        Ok(Position {
+            line: u32::try_from(line)?,
+            character: u32::try_from(character)?,
+        })
+    }
+}
+
+impl Range {
+    pub fn new(start_line: u32, start_character: u32, end_line: u32, end_character: u32) -> Self {
+        Range {
+            start: Position::new(start_line, start_character),
+            end: Position::new(end_line, end_character),
+        }
+    }
+
+    /// Converts a byte range of `text` into a range in the units of the negotiated
+    /// `encoding`, see `LspClient::position_encoding`.
+    pub fn from_byte_range(
+        text: &str,
+        start: usize,
+        end: usize,
+        encoding: PositionEncoding,
+    ) -> Result<Self> {
+        if start > end {
+            return Err(anyhow!("Byte range {}..{} is reversed", start, end));
+        }
+        Ok(Range {
+            start: Position::from_byte_offset(text, start, encoding)?,
+            end: Position::from_byte_offset(text, end, encoding)?,
+        })
+    }
+}
+
+/// The unit `Position::character` is counted in, negotiated during `initialize`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PositionEncoding {
+    #[serde(rename = "utf-8")]
+    Utf8,
+    /// The default, which every server must support.
+    #[default]
+    #[serde(rename = "utf-16")]
+    Utf16,
+    #[serde(rename = "utf-32")]
+    Utf32,
+}
+
+/// A symbol returned by `workspace/symbol` and resolved by `workspaceSymbol/resolve`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WorkspaceSymbol {
+    pub name: String,
+    /// The `SymbolKind` of the symbol, e.g. 12 for a function.
+    pub kind: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<u32>>,
+    #[serde(rename = "containerName", skip_serializing_if = "Option::is_none")]
+    pub container_name: Option<String>,
+    pub location: WorkspaceSymbolLocation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+/// A symbol of a document in the nested form of `textDocument/documentSymbol`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentSymbol {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// The `SymbolKind` of the symbol, e.g. 12 for a function.
+    pub kind: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<u32>>,
+    /// The full range of the symbol, e.g. including the body of a function.
+    pub range: Range,
+    /// The part of `range` to select, e.g. the name of a function.
+    pub selection_range: Range,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub children: Option<Vec<DocumentSymbol>>,
+}
+
+/// A symbol of a document in the flat form of `textDocument/documentSymbol`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SymbolInformation {
+    pub name: String,
+    /// The `SymbolKind` of the symbol, e.g. 12 for a function.
+    pub kind: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<u32>>,
+    pub location: Location,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub container_name: Option<String>,
+}
+
+/// The result of a `textDocument/documentSymbol` request. The nested form is tried first,
+/// so an empty list is returned as `Nested`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum DocumentSymbolResponse {
+    Nested(Vec<DocumentSymbol>),
+    Flat(Vec<SymbolInformation>),
+}
+
+/// A function, method or constructor returned by `textDocument/prepareCallHierarchy`.
+/// Send it back as is to get its incoming or outgoing calls.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CallHierarchyItem {
+    pub name: String,
+    /// The `SymbolKind` of the item, e.g. 6 for a method.
+    pub kind: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<u32>>,
+    /// E.g. the signature, which tells overloads apart.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub uri: String,
+    pub range: Range,
+    pub selection_range: Range,
+    /// Preserved between the prepare and the calls requests.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+/// A caller of a call hierarchy item.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CallHierarchyIncomingCall {
+    pub from: CallHierarchyItem,
+    /// The ranges of the calls within `from`.
+    pub from_ranges: Vec<Range>,
+}
+
+/// A callee of a call hierarchy item.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CallHierarchyOutgoingCall {
+    pub to: CallHierarchyItem,
+    /// The ranges of the calls within the item the calls were requested for.
+    pub from_ranges: Vec<Range>,
+}
+
+/// The location of a workspace symbol. Servers that resolve symbols lazily
+/// only send the URI and fill in the range on `workspaceSymbol/resolve`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum WorkspaceSymbolLocation {
+    Location(Location),
+    Uri { uri: String },
+}
+
+/// The result of a `textDocument/semanticTokens/full` or `textDocument/semanticTokens/range`
+/// request. `data` holds the tokens in the relative, integer encoded form sent by the server.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SemanticTokens {
+    #[serde(rename = "resultId", skip_serializing_if = "Option::is_none")]
+    pub result_id: Option<String>,
+    pub data: Vec<u32>,
+}
+
+/// The result of a `textDocument/semanticTokens/full/delta` request.
+/// Servers may answer with either a full token set or edits to the previous one.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum SemanticTokensDeltaResult {
+    Delta(SemanticTokensDelta),
+    Full(SemanticTokens),
+}
+
+/// Edits to apply to a previously received token array.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SemanticTokensDelta {
+    #[serde(rename = "resultId", skip_serializing_if = "Option::is_none")]
+    pub result_id: Option<String>,
+    pub edits: Vec<SemanticTokensEdit>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SemanticTokensEdit {
+    pub start: u32,
+    #[serde(rename = "deleteCount")]
+    pub delete_count: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Vec<u32>>,
+}
+
+impl SemanticTokensDelta {
+    /// Applies the edits to the tokens of the previous result and returns the new token set.
+    /// Edit offsets refer to the previous data array, so they are applied in order of `start`.
+    pub fn apply(&self, previous: &SemanticTokens) -> Result<SemanticTokens> {
+        let mut edits: Vec<&SemanticTokensEdit> = self.edits.iter().collect();
+        edits.sort_by_key(|edit| edit.start);
+
+        let mut data = Vec::with_capacity(previous.data.len());
+        let mut cursor = 0;
+        for edit in edits {
+            let start = edit.start as usize;
+            let end = start + edit.delete_count as usize;
+            if start < cursor || end > previous.data.len() {
+                bail!(
+                    "Semantic tokens edit {}..{} is out of bounds or overlaps another edit.",
+                    start,
+                    end
+                );
+            }
+            data.extend_from_slice(&previous.data[cursor..start]);
+            data.extend(edit.data.iter().flatten());
+            cursor = end;
+        }
+        data.extend_from_slice(&previous.data[cursor..]);
+
+        Ok(SemanticTokens {
+            result_id: self.result_id.clone(),
+            data,
+        })
+    }
+}
+
+/// The legend the server advertises in its `semanticTokensProvider` capability,
+/// used to map the integer encoded token types and modifiers back to names.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SemanticTokensLegend {
+    #[serde(rename = "tokenTypes")]
+    pub token_types: Vec<String>,
+    #[serde(rename = "tokenModifiers")]
+    pub token_modifiers: Vec<String>,
+}
+
+/// A single semantic token with an absolute position and resolved names.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SemanticToken {
+    pub line: u32,
+    pub start: u32,
+    pub length: u32,
+    pub token_type: String,
+    pub token_modifiers: Vec<String>,
+}
+
+impl SemanticTokens {
+    /// Decodes the relative token data into absolute tokens using the server's legend.
+    pub fn decode(&self, legend: &SemanticTokensLegend) -> Result<Vec<SemanticToken>> {
+        if !self.data.len().is_multiple_of(5) {
+            bail!(
+                "Semantic token data length {} is not a multiple of 5.",
+                self.data.len()
+            );
+        }
+
+        let mut tokens = Vec::with_capacity(self.data.len() / 5);
+        let (mut line, mut start) = (0, 0);
+        for chunk in self.data.chunks(5) {
+            let (delta_line, delta_start, length, token_type, modifiers) =
+                (chunk[0], chunk[1], chunk[2], chunk[3], chunk[4]);
+            if delta_line > 0 {
+                line += delta_line;
+                start = delta_start;
+            } else {
+                start += delta_start;
+            }
+
+            let token_type = legend
+                .token_types
+                .get(token_type as usize)
+                .ok_or_else(|| anyhow::anyhow!("Unknown semantic token type {}.", token_type))?;
+            let token_modifiers = legend
+                .token_modifiers
+                .iter()
+                .enumerate()
+                .filter(|(bit, _)| *bit < 32 && modifiers & (1 << bit) != 0)
+                .map(|(_, modifier)| modifier.clone())
+                .collect();
+
+            tokens.push(SemanticToken {
+                line,
+                start,
+                length,
+                token_type: token_type.clone(),
+                token_modifiers,
+            });
+        }
+        Ok(tokens)
+    }
+}
+
+/// A textual edit applicable to a text document.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TextEdit {
+    pub range: Range,
+    #[serde(rename = "newText")]
+    pub new_text: String,
+}
+
+/// How a document should be formatted.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FormattingOptions {
+    pub tab_size: u32,
+    pub insert_spaces: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trim_trailing_whitespace: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub insert_final_newline: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trim_final_newlines: Option<bool>,
+}
+
+impl FormattingOptions {
+    pub fn new(tab_size: u32, insert_spaces: bool) -> Self {
+        FormattingOptions {
+            tab_size,
+            insert_spaces,
+            trim_trailing_whitespace: None,
+            insert_final_newline: None,
+            trim_final_newlines: None,
+        }
+    }
+}
+
+/// Changes to many resources, as returned by e.g. `workspace/willRenameFiles`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct WorkspaceEdit {
+    /// Keyed by URI. A `BTreeMap` so that edits serialize in the same order on every run.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changes: Option<BTreeMap<String, Vec<TextEdit>>>,
+    #[serde(
+        rename = "documentChanges",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub document_changes: Option<Vec<DocumentChange>>,
+}
+
+/// An entry of `WorkspaceEdit::document_changes`: either edits to a single document or
+/// a create/rename/delete of a resource.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum DocumentChange {
+    Operation(ResourceOperation),
+    Edit(TextDocumentEdit),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TextDocumentEdit {
+    #[serde(rename = "textDocument")]
+    pub text_document: OptionalVersionedTextDocumentIdentifier,
+    pub edits: Vec<TextEdit>,
+}
+
+/// Identifies a text document by its URI.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TextDocumentIdentifier {
+    pub uri: String,
+}
+
+/// Identifies a specific version of a text document, e.g. the version a `didChange`
+/// brings the document to.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct VersionedTextDocumentIdentifier {
+    pub uri: String,
+    pub version: i32,
+}
+
+/// Identifies the version of a text document an edit was computed for. A `None` version
+/// means the edit applies to whatever version the client has. Clients should reject edits
+/// for a version other than the current one.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OptionalVersionedTextDocumentIdentifier {
+    pub uri: String,
+    pub version: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum ResourceOperation {
+    Create {
+        uri: String,
+    },
+    Rename {
+        #[serde(rename = "oldUri")]
+        old_uri: String,
+        #[serde(rename = "newUri")]
+        new_uri: String,
+    },
+    Delete {
+        uri: String,
+    },
+}
+
+/// Additional information about the context in which a completion request is triggered.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CompletionContext {
+    #[serde(rename = "triggerKind")]
+    pub trigger_kind: CompletionTriggerKind,
+    /// The character that triggered completion, set when `trigger_kind` is `TriggerCharacter`.
+    #[serde(
+        rename = "triggerCharacter",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub trigger_character: Option<String>,
+}
+
+/// How a completion was triggered.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum CompletionTriggerKind {
+    /// Typing an identifier, manual invocation or via API.
+    Invoked,
+    /// Typing one of the server's `triggerCharacters`.
+    TriggerCharacter,
+    /// Re-triggered because the previous completion list was incomplete.
+    TriggerForIncompleteCompletions,
+}
+
+impl TryFrom<u8> for CompletionTriggerKind {
+    type Error = String;
+
+    fn try_from(kind: u8) -> std::result::Result<Self, String> {
+        match kind {
+            1 => Ok(CompletionTriggerKind::Invoked),
+            2 => Ok(CompletionTriggerKind::TriggerCharacter),
+            3 => Ok(CompletionTriggerKind::TriggerForIncompleteCompletions),
+            other => Err(format!("Invalid completion trigger kind {}", other)),
+        }
+    }
+}
+
+impl From<CompletionTriggerKind> for u8 {
+    fn from(kind: CompletionTriggerKind) -> Self {
+        match kind {
+            CompletionTriggerKind::Invoked => 1,
+            CompletionTriggerKind::TriggerCharacter => 2,
+            CompletionTriggerKind::TriggerForIncompleteCompletions => 3,
+        }
+    }
+}
+
+/// The result of a `textDocument/hover` request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HoverResult {
+    pub contents: HoverContents,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<Range>,
+}
+
+impl HoverResult {
+    /// Returns the format the server used for the contents. The deprecated `MarkedString`
+    /// forms are rendered as markdown. Servers are not bound to the formats the client
+    /// advertised, so this may be one the client did not ask for.
+    pub fn format(&self) -> MarkupKind {
+        match &self.contents {
+            HoverContents::Markup(markup) => markup.kind,
+            HoverContents::MarkedString(_) | HoverContents::MarkedStrings(_) => {
+                MarkupKind::Markdown
+            }
+        }
+    }
+}
+
+/// Hover contents, either `MarkupContent` or the deprecated `MarkedString` forms.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum HoverContents {
+    Markup(MarkupContent),
+    MarkedString(MarkedString),
+    MarkedStrings(Vec<MarkedString>),
+}
+
+impl HoverContents {
+    /// Returns the text of the contents regardless of their form. Several `MarkedString`s
+    /// are joined with blank lines, code blocks contribute their code without the language.
+    /// Markdown is returned as is, without rendering it.
+    pub fn as_plain_text(&self) -> String {
+        match self {
+            HoverContents::Markup(markup) => markup.value.clone(),
+            HoverContents::MarkedString(marked) => marked.as_plain_text().to_string(),
+            HoverContents::MarkedStrings(marked) => marked
+                .iter()
+                .map(MarkedString::as_plain_text)
+                .collect::<Vec<_>>()
+                .join("\n\n"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MarkupContent {
+    pub kind: MarkupKind,
+    pub value: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkupKind {
+    PlainText,
+    Markdown,
+}
+
+impl MarkupKind {
+    /// Returns the name of the kind as used in `contentFormat` capabilities.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MarkupKind::PlainText => "plaintext",
+            MarkupKind::Markdown => "markdown",
+        }
+    }
+}
+
+/// The documentation of a completion item, signature or parameter: either a plain string
+/// or `MarkupContent`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum Documentation {
+    String(String),
+    Markup(MarkupContent),
+}
+
+impl Documentation {
+    /// Returns the text of the documentation regardless of its form.
+    /// Markdown is returned as is, without rendering it.
+    pub fn as_plain_text(&self) -> &str {
+        match self {
+            Documentation::String(text) => text,
+            Documentation::Markup(markup) => &markup.value,
+        }
+    }
+}
+
+/// The result of a `textDocument/signatureHelp` request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SignatureHelp {
+    pub signatures: Vec<SignatureInformation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_signature: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_parameter: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SignatureInformation {
+    pub label: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub documentation: Option<Documentation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<Vec<ParameterInformation>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_parameter: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ParameterInformation {
+    pub label: ParameterLabel,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub documentation: Option<Documentation>,
+}
+
+/// The label of a parameter: either a substring of the signature label, or the start
+/// and end offsets of it within the signature label, in UTF-16 code units.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ParameterLabel {
+    String(String),
+    Offsets([u32; 2]),
+}
+
+/// The debugger state a `textDocument/inlineValue` request is made in.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct InlineValueContext {
+    /// The id of the stack frame the debugger is stopped in.
+    pub frame_id: i32,
+    /// The range of the document where execution stopped.
+    pub stopped_location: Range,
+}
+
+/// A value shown inline while debugging, told apart by its fields.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum InlineValue {
+    /// Text shown as is.
+    Text { range: Range, text: String },
+    /// A variable the client looks up, by `variable_name` or else by the text at `range`.
+    VariableLookup {
+        range: Range,
+        #[serde(
+            rename = "variableName",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        variable_name: Option<String>,
+        #[serde(rename = "caseSensitiveLookup")]
+        case_sensitive_lookup: bool,
+    },
+    /// An expression the client evaluates, `expression` or else the text at `range`.
+    EvaluatableExpression {
+        range: Range,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expression: Option<String>,
+    },
+}
+
+/// A markdown string, or a code block in the given language.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum MarkedString {
+    String(String),
+    LanguageString { language: String, value: String },
+}
+
+impl MarkedString {
+    fn as_plain_text(&self) -> &str {
+        match self {
+            MarkedString::String(text) => text,
+            MarkedString::LanguageString { value, .. } => value,
+        }
+    }
+}
+
+/// The result of a `textDocument/completion` request, see `ResponseMessage::handle_completion`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionList {
+    pub is_incomplete: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub item_defaults: Option<CompletionItemDefaults>,
+    pub items: Vec<CompletionItem>,
+}
+
+/// Values shared by all items of a `CompletionList` which don't set them themselves.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionItemDefaults {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit_characters: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edit_range: Option<CompletionEditRange>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub insert_text_format: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub insert_text_mode: Option<InsertTextMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum CompletionEditRange {
+    Range(Range),
+    InsertReplace { insert: Range, replace: Range },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CompletionItem {
+    pub label: String,
+    /// The `CompletionItemKind` of the item, e.g. 3 for a function.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub documentation: Option<Documentation>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub insert_text: Option<String>,
+    /// 1 for plain text, 2 for a snippet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub insert_text_format: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub insert_text_mode: Option<InsertTextMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text_edit: Option<CompletionTextEdit>,
+    /// The text to insert when the edit range comes from `CompletionItemDefaults`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text_edit_text: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit_characters: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+}
+
+/// The edit of a completion item, told apart by its fields: a `TextEdit` has a `range`,
+/// an `InsertReplaceEdit` has both an `insert` and a `replace` range.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum CompletionTextEdit {
+    Edit(TextEdit),
+    InsertReplace(InsertReplaceEdit),
+}
+
+/// A completion edit the client applies over either of two ranges, depending on whether
+/// the user chose to insert the completion or to replace the word under the cursor.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct InsertReplaceEdit {
+    pub new_text: String,
+    pub insert: Range,
+    pub replace: Range,
+}
+
+/// How whitespace and indentation in a completion's insert text are handled.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(try_from = "u8", into = "u8")]
+pub enum InsertTextMode {
+    /// The text is inserted as is, e.g. when the server already indented it.
+    AsIs,
+    /// Following lines are indented like the line the completion is inserted on.
+    AdjustIndentation,
+}
+
+impl TryFrom<u8> for InsertTextMode {
+    type Error = String;
+
+    fn try_from(mode: u8) -> std::result::Result<Self, String> {
+        match mode {
+            1 => Ok(InsertTextMode::AsIs),
+            2 => Ok(InsertTextMode::AdjustIndentation),
+            other => Err(format!("Invalid insert text mode {}", other)),
+        }
+    }
+}
+
+impl From<InsertTextMode> for u8 {
+    fn from(mode: InsertTextMode) -> Self {
+        match mode {
+            InsertTextMode::AsIs => 1,
+            InsertTextMode::AdjustIndentation => 2,
+        }
+    }
+}
+
+impl CompletionList {
+    /// Fills in the fields each item leaves unset from `item_defaults`.
+    /// Values set on an item always take precedence over the defaults.
+    pub fn merge_item_defaults(&mut self) {
+        let Some(defaults) = &self.item_defaults else {
+            return;
+        };
+        for item in &mut self.items {
+            if item.commit_characters.is_none() {
+                item.commit_characters = defaults.commit_characters.clone();
+            }
+            if item.insert_text_format.is_none() {
+                item.insert_text_format = defaults.insert_text_format;
+            }
+            if item.insert_text_mode.is_none() {
+                item.insert_text_mode = defaults.insert_text_mode;
+            }
+            if item.data.is_none() {
+                item.data = defaults.data.clone();
+            }
+            if item.text_edit.is_none() {
+                let new_text = item
+                    .text_edit_text
+                    .clone()
+                    .unwrap_or_else(|| item.label.clone());
+                item.text_edit = defaults.edit_range.clone().map(|range| match range {
+                    CompletionEditRange::Range(range) => {
+                        CompletionTextEdit::Edit(TextEdit { range, new_text })
+                    }
+                    CompletionEditRange::InsertReplace { insert, replace } => {
+                        CompletionTextEdit::InsertReplace(InsertReplaceEdit {
+                            new_text,
+                            insert,
+                            replace,
+                        })
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// A file rename, as sent with `workspace/willRenameFiles` and `workspace/didRenameFiles`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileRename {
+    #[serde(rename = "oldUri")]
+    pub old_uri: String,
+    #[serde(rename = "newUri")]
+    pub new_uri: String,
+}
+
+/// A file create, as sent with `workspace/willCreateFiles` and `workspace/didCreateFiles`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileCreate {
+    pub uri: String,
+}
+
+/// A file delete, as sent with `workspace/willDeleteFiles` and `workspace/didDeleteFiles`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FileDelete {
+    pub uri: String,
+}
+
+/// A change to a text document. Without a range, `text` replaces the whole document.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TextDocumentContentChangeEvent {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range: Option<Range>,
+    pub text: String,
+}
+
+/// A text document as transferred to the server when it is opened.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TextDocumentItem {
+    pub uri: String,
+    #[serde(rename = "languageId")]
+    pub language_id: String,
+    pub version: i32,
+    pub text: String,
 }
 
 impl RequestMessage {
     /// Helper function to create a new `initialize` request message.
     /// id - The ID of the request message.
-    /// process_id - The process ID of the client. (usually `std::process::id()`)
+    /// process_id - The process ID of the client. (usually `Some(std::process::id())`)
+    ///   `None` is sent as `null`, meaning the client was not started by another process.
     /// root_uri - The root URI of the workspace. (e.g. `file://path/to/code`)
     /// client_name - The name of the client. (e.g. `vim-go`)
+    /// client_version - The version of the client, omitted from the message when `None`.
     /// workspace_folders - List of folders that the lsp needs context for.
-    /// TODO: This function is currently a bit opinionated towards textdefintion.
-    /// To have a custom initialize message, the workaround for now is to directly
-    /// create a `RequestMessage` with desired capabilities.
+    /// The default `ClientCapabilities` are advertised, use `new_initialize_with_capabilities`
+    /// to customize them.
     pub fn new_initialize(
         id: u32,
-        process_id: u32,
+        process_id: Option<u32>,
+        root_uri: String,
+        client_name: String,
+        client_version: Option<String>,
+        workspace_folders: Vec<WorkspaceFolder>,
+    ) -> Self {
+        Self::new_initialize_with_capabilities(
+            id,
+            process_id,
+            root_uri,
+            client_name,
+            client_version,
+            workspace_folders,
+            ClientCapabilities::default(),
+        )
+    }
+
+    /// Helper function to create a new `initialize` request message advertising the given
+    /// capabilities. The arguments are the same as for `new_initialize`.
+    /// capabilities - The client capabilities, e.g. built from `ClientCapabilities::default()`.
+    pub fn new_initialize_with_capabilities(
+        id: u32,
+        process_id: Option<u32>,
         root_uri: String,
         client_name: String,
-        client_version: String,
+        client_version: Option<String>,
         workspace_folders: Vec<WorkspaceFolder>,
+        capabilities: ClientCapabilities,
     ) -> Self {
         let client_info = ClientInfo {
             name: client_name,
             version: client_version,
         };
 
-        let capabilities = ClientCapabilities {
-            workspace: Some(CapabilitiesWorkspace {
-                workspace_folders: true,
-                did_change_configuration: DidChangeConfiguration {
-                    dynamic_registration: true,
-                },
-                workspace_edit: WorkspaceEdit {
-                    document_changes: true,
-                },
-                configuration: true,
-            }),
-            text_document: Some(CapabilitiesTextDocument {
-                hover: Hover {
-                    content_format: vec!["plaintext".to_string()],
+        Self::new_initialize_from_params(
+            id,
+            InitializeParams {
+                process_id,
+                root_uri,
+                client_info: Some(client_info),
+                capabilities,
+                workspace_folders: Some(workspace_folders),
+            },
+        )
+    }
+
+    /// Helper function to create a new `initialize` request message from fully specified
+    /// params, e.g. to omit `clientInfo` by setting it to `None`.
+    /// id - The ID of the request message.
+    /// params - The params of the request.
+    pub fn new_initialize_from_params(id: u32, params: InitializeParams) -> Self {
+        RequestMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: serde_json::Value::from(id),
+            method: "initialize".to_string(),
+            notification: 0,
+            params: serde_json::to_value(params).unwrap(),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/definition` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
+    /// line - The line number of the cursor position.
+    /// character - The the cursor position of the character we want to get the definition of.
+    pub fn new_get_definition(id: u32, uri: String, position: Position) -> Self {
+        RequestMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: serde_json::Value::from(id),
+            method: "textDocument/definition".to_string(),
+            notification: 0,
+            params: serde_json::json!({
+                "textDocument": {
+                    "uri": uri
+                },
+                "position": {
+                    "line": position.line,
+                    "character": position.character,
+                }
+            }),
+        }
+    }
+
+    /// Helper function to create a new `initialize` request message with arbitrary params,
+    /// e.g. to advertise capabilities this crate does not model yet. Prefer the typed
+    /// builders where possible; the params are sent as is, without any validation.
+    /// id - The ID of the request message.
+    /// params - The params of the request.
+    pub fn new_initialize_raw(id: u32, params: serde_json::Value) -> Self {
+        Self::new_raw(id, "initialize".to_string(), params)
+    }
+
+    /// Helper function to create a request message for an arbitrary method.
+    /// id - The ID of the request message.
+    /// method - The method to call. (e.g. `gopls/gc_details`)
+    /// params - The params of the request, which JSON-RPC allows to be an object or an array.
+    pub fn new_raw(id: u32, method: String, params: serde_json::Value) -> Self {
+        RequestMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: serde_json::Value::from(id),
+            method,
+            notification: 0,
+            params,
+        }
+    }
+
+    /// Helper function to create a new `shutdown` request message.
+    /// After answering it the server must not accept any request other than `exit`.
+    pub fn new_shutdown(id: u32) -> Self {
+        RequestMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: serde_json::Value::from(id),
+            method: "shutdown".to_string(),
+            notification: 0,
+            params: serde_json::Value::Null,
+        }
+    }
+
+    /// Helper function to create a new `textDocument/hover` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
+    /// position - The position to get hover information for.
+    pub fn new_hover(id: u32, uri: String, position: Position) -> Self {
+        RequestMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: serde_json::Value::from(id),
+            method: "textDocument/hover".to_string(),
+            notification: 0,
+            params: serde_json::json!({
+                "textDocument": {
+                    "uri": uri
+                },
+                "position": position,
+            }),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/onTypeFormatting` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
+    /// position - The position after the typed character.
+    /// ch - The character that was typed, e.g. `}`.
+    /// options - How to format the document.
+    pub fn new_on_type_formatting(
+        id: u32,
+        uri: String,
+        position: Position,
+        ch: String,
+        options: FormattingOptions,
+    ) -> Self {
+        RequestMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: serde_json::Value::from(id),
+            method: "textDocument/onTypeFormatting".to_string(),
+            notification: 0,
+            params: serde_json::json!({
+                "textDocument": {
+                    "uri": uri
+                },
+                "position": position,
+                "ch": ch,
+                "options": options,
+            }),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/inlineValue` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
+    /// range - The visible range of the document to get inline values for.
+    /// context - Where the debugger is stopped.
+    pub fn new_inline_value(
+        id: u32,
+        uri: String,
+        range: Range,
+        context: InlineValueContext,
+    ) -> Self {
+        RequestMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: serde_json::Value::from(id),
+            method: "textDocument/inlineValue".to_string(),
+            notification: 0,
+            params: serde_json::json!({
+                "textDocument": {
+                    "uri": uri
+                },
+                "range": range,
+                "context": context,
+            }),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/signatureHelp` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
+    /// position - The position inside the call's arguments.
+    pub fn new_signature_help(id: u32, uri: String, position: Position) -> Self {
+        RequestMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: serde_json::Value::from(id),
+            method: "textDocument/signatureHelp".to_string(),
+            notification: 0,
+            params: serde_json::json!({
+                "textDocument": {
+                    "uri": uri
+                },
+                "position": position,
+            }),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/completion` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
+    /// position - The position to complete at.
+    /// context - How the completion was triggered, omitted from the message when `None`.
+    pub fn new_completion(
+        id: u32,
+        uri: String,
+        position: Position,
+        context: Option<CompletionContext>,
+    ) -> Self {
+        let mut params = serde_json::json!({
+            "textDocument": {
+                "uri": uri
+            },
+            "position": position,
+        });
+        if let Some(context) = context {
+            params["context"] = serde_json::to_value(context).unwrap();
+        }
+
+        RequestMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: serde_json::Value::from(id),
+            method: "textDocument/completion".to_string(),
+            notification: 0,
+            params,
+        }
+    }
+
+    /// Helper function to create the follow-up `textDocument/completion` request an editor
+    /// sends as the user keeps typing after an incomplete completion list.
+    /// Returns `None` if `previous` is complete, in which case editors filter it locally.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
+    /// position - The position to complete at, after the characters typed since.
+    /// previous - The list returned for the previous completion request.
+    pub fn new_completion_continuation(
+        id: u32,
+        uri: String,
+        position: Position,
+        previous: &CompletionList,
+    ) -> Option<Self> {
+        if !previous.is_incomplete {
+            return None;
+        }
+        Some(Self::new_completion(
+            id,
+            uri,
+            position,
+            Some(CompletionContext {
+                trigger_kind: CompletionTriggerKind::TriggerForIncompleteCompletions,
+                trigger_character: None,
+            }),
+        ))
+    }
+
+    /// Helper function to create a new `textDocument/documentSymbol` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document to get the symbols of.
+    pub fn new_document_symbol(id: u32, uri: String) -> Self {
+        RequestMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: serde_json::Value::from(id),
+            method: "textDocument/documentSymbol".to_string(),
+            notification: 0,
+            params: serde_json::json!({
+                "textDocument": {
+                    "uri": uri
+                }
+            }),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/semanticTokens/full` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document to get the tokens of.
+    pub fn new_semantic_tokens_full(id: u32, uri: String) -> Self {
+        RequestMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: serde_json::Value::from(id),
+            method: "textDocument/semanticTokens/full".to_string(),
+            notification: 0,
+            params: serde_json::json!({
+                "textDocument": {
+                    "uri": uri
+                }
+            }),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/semanticTokens/range` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document to get the tokens of.
+    /// range - The range to get the tokens for, usually the visible part of the document.
+    pub fn new_semantic_tokens_range(id: u32, uri: String, range: Range) -> Self {
+        RequestMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: serde_json::Value::from(id),
+            method: "textDocument/semanticTokens/range".to_string(),
+            notification: 0,
+            params: serde_json::json!({
+                "textDocument": {
+                    "uri": uri
+                },
+                "range": range,
+            }),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/semanticTokens/full/delta` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document to get the tokens of.
+    /// previous_result_id - The `resultId` of the last semantic tokens result for the document.
+    pub fn new_semantic_tokens_delta(id: u32, uri: String, previous_result_id: String) -> Self {
+        RequestMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: serde_json::Value::from(id),
+            method: "textDocument/semanticTokens/full/delta".to_string(),
+            notification: 0,
+            params: serde_json::json!({
+                "textDocument": {
+                    "uri": uri
+                },
+                "previousResultId": previous_result_id,
+            }),
+        }
+    }
+
+    /// Helper function to create a new `workspaceSymbol/resolve` request message.
+    /// id - The ID of the request message.
+    /// symbol - A symbol previously returned by `workspace/symbol`, sent back as is.
+    pub fn new_workspace_symbol_resolve(id: u32, symbol: WorkspaceSymbol) -> Self {
+        RequestMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: serde_json::Value::from(id),
+            method: "workspaceSymbol/resolve".to_string(),
+            notification: 0,
+            params: serde_json::to_value(symbol).unwrap(),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/prepareCallHierarchy` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
+    /// position - The position of the function, method or constructor.
+    pub fn new_prepare_call_hierarchy(id: u32, uri: String, position: Position) -> Self {
+        RequestMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: serde_json::Value::from(id),
+            method: "textDocument/prepareCallHierarchy".to_string(),
+            notification: 0,
+            params: serde_json::json!({
+                "textDocument": {
+                    "uri": uri
+                },
+                "position": position,
+            }),
+        }
+    }
+
+    /// Helper function to create a new `callHierarchy/incomingCalls` request message.
+    /// id - The ID of the request message.
+    /// item - One of the items returned by `textDocument/prepareCallHierarchy`.
+    pub fn new_incoming_calls(id: u32, item: CallHierarchyItem) -> Self {
+        RequestMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: serde_json::Value::from(id),
+            method: "callHierarchy/incomingCalls".to_string(),
+            notification: 0,
+            params: serde_json::json!({ "item": item }),
+        }
+    }
+
+    /// Helper function to create a new `callHierarchy/outgoingCalls` request message.
+    /// id - The ID of the request message.
+    /// item - One of the items returned by `textDocument/prepareCallHierarchy`.
+    pub fn new_outgoing_calls(id: u32, item: CallHierarchyItem) -> Self {
+        RequestMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: serde_json::Value::from(id),
+            method: "callHierarchy/outgoingCalls".to_string(),
+            notification: 0,
+            params: serde_json::json!({ "item": item }),
+        }
+    }
+
+    /// Helper function to create a new `workspace/executeCommand` request message.
+    /// id - The ID of the request message.
+    /// command - The identifier of the command to run. (e.g. `gopls.tidy`)
+    /// arguments - The positional arguments passed to the command.
+    /// work_done_token - A token for the server to report the command's progress with via
+    /// `$/progress`, omitted from the message when `None`.
+    pub fn new_execute_command(
+        id: u32,
+        command: String,
+        arguments: Vec<serde_json::Value>,
+        work_done_token: Option<ProgressToken>,
+    ) -> Self {
+        let mut params = serde_json::json!({
+            "command": command,
+            "arguments": arguments,
+        });
+        if let Some(token) = work_done_token {
+            params["workDoneToken"] = serde_json::to_value(token).unwrap();
+        }
+
+        RequestMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: serde_json::Value::from(id),
+            method: "workspace/executeCommand".to_string(),
+            notification: 0,
+            params,
+        }
+    }
+
+    /// Helper function to create a new `workspace/willRenameFiles` request message.
+    /// The server may answer with a `WorkspaceEdit` to apply before the files are renamed,
+    /// see `ResponseMessage::handle_workspace_edit`.
+    /// id - The ID of the request message.
+    /// renames - The files that are about to be renamed.
+    pub fn new_will_rename_files(id: u32, renames: Vec<FileRename>) -> Self {
+        RequestMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: serde_json::Value::from(id),
+            method: "workspace/willRenameFiles".to_string(),
+            notification: 0,
+            params: serde_json::json!({ "files": renames }),
+        }
+    }
+
+    /// Helper function to create a new `workspace/willCreateFiles` request message.
+    /// The server may answer with a `WorkspaceEdit` to apply before the files are created,
+    /// see `ResponseMessage::handle_workspace_edit`.
+    /// id - The ID of the request message.
+    /// files - The files that are about to be created.
+    pub fn new_will_create_files(id: u32, files: Vec<FileCreate>) -> Self {
+        RequestMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: serde_json::Value::from(id),
+            method: "workspace/willCreateFiles".to_string(),
+            notification: 0,
+            params: serde_json::json!({ "files": files }),
+        }
+    }
+
+    /// Helper function to create a new `workspace/willDeleteFiles` request message.
+    /// The server may answer with a `WorkspaceEdit` to apply before the files are deleted,
+    /// see `ResponseMessage::handle_workspace_edit`.
+    /// id - The ID of the request message.
+    /// files - The files that are about to be deleted.
+    pub fn new_will_delete_files(id: u32, files: Vec<FileDelete>) -> Self {
+        RequestMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: serde_json::Value::from(id),
+            method: "workspace/willDeleteFiles".to_string(),
+            notification: 0,
+            params: serde_json::json!({ "files": files }),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/diagnostic` request message.
+    /// id - The ID of the request message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
+    /// previous_result_id - The `resultId` of the last report for this document, if any,
+    ///   allowing the server to answer with an unchanged report.
+    pub fn new_document_diagnostic(
+        id: u32,
+        uri: String,
+        previous_result_id: Option<String>,
+    ) -> Self {
+        let mut params = serde_json::json!({
+            "textDocument": {
+                "uri": uri
+            }
+        });
+        if let Some(previous_result_id) = previous_result_id {
+            params["previousResultId"] = serde_json::Value::from(previous_result_id);
+        }
+
+        RequestMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: serde_json::Value::from(id),
+            method: "textDocument/diagnostic".to_string(),
+            notification: 0,
+            params,
+        }
+    }
+
+    /// Helper function to create a new `workspace/diagnostic` request message.
+    /// id - The ID of the request message.
+    /// previous_result_ids - The last `resultId` reported for each document, allowing the
+    ///   server to answer with unchanged reports.
+    pub fn new_workspace_diagnostic(id: u32, previous_result_ids: Vec<PreviousResultId>) -> Self {
+        RequestMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: serde_json::Value::from(id),
+            method: "workspace/diagnostic".to_string(),
+            notification: 0,
+            params: serde_json::json!({ "previousResultIds": previous_result_ids }),
+        }
+    }
+}
+
+impl RequestMessage {
+    /// Asks the server to stream the result via `$/progress` notifications with this token.
+    /// Only applies to requests whose params are an object.
+    pub fn with_partial_result_token(mut self, token: ProgressToken) -> Self {
+        if let Some(params) = self.params.as_object_mut() {
+            params.insert(
+                "partialResultToken".to_string(),
+                serde_json::to_value(token).unwrap(),
+            );
+        }
+        self
+    }
+
+    /// Merges extra fields into the params, e.g. server specific extensions such as a
+    /// result limit on `textDocument/completion`. Fields already present are overwritten.
+    /// Only applies to requests whose params are an object or absent, and to an object `extra`.
+    pub fn with_extra(mut self, extra: serde_json::Value) -> Self {
+        let serde_json::Value::Object(extra) = extra else {
+            return self;
+        };
+        if self.params.is_null() {
+            self.params = serde_json::Value::Object(serde_json::Map::new());
+        }
+        if let Some(params) = self.params.as_object_mut() {
+            params.extend(extra);
+        }
+        self
+    }
+}
+
+impl NotificationMessage {
+    /// Helper function to create a new `initialized` notification message.
+    /// This message is sent by the client to the server once it has finished initializing
+    /// and signals that the client is ready to receive requests.
+    pub fn new_initialized() -> Self {
+        NotificationMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            method: "initialized".to_string(),
+            params: serde_json::Value::Object(serde_json::Map::new()),
+        }
+    }
+
+    /// Helper function to create a new `$/cancelRequest` notification message.
+    /// id - The ID of the request to cancel.
+    pub fn new_cancel_request(id: RequestId) -> Self {
+        NotificationMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            method: "$/cancelRequest".to_string(),
+            params: serde_json::to_value(CancelParams { id }).unwrap(),
+        }
+    }
+
+    /// Helper function to create a new `exit` notification message.
+    /// This asks the server to exit its process and is sent after `shutdown`.
+    pub fn new_exit() -> Self {
+        NotificationMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            method: "exit".to_string(),
+            params: serde_json::Value::Null,
+        }
+    }
+
+    /// Helper function to create a new `textDocument/didOpen` notification message.
+    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
+    /// language_id - The language of the document. (e.g. `go`)
+    /// version - The version of the document, incremented after each change.
+    /// text - The full content of the document.
+    pub fn new_did_open(uri: String, language_id: String, version: i32, text: String) -> Self {
+        NotificationMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            method: "textDocument/didOpen".to_string(),
+            params: serde_json::json!({
+                "textDocument": TextDocumentItem {
+                    uri,
+                    language_id,
+                    version,
+                    text,
+                }
+            }),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/didChange` notification message.
+    /// uri - The URI of the text document that changed.
+    /// version - The version of the document after the changes.
+    /// content_changes - The changes, applied by the server in order.
+    pub fn new_did_change(
+        uri: String,
+        version: i32,
+        content_changes: Vec<TextDocumentContentChangeEvent>,
+    ) -> Self {
+        NotificationMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            method: "textDocument/didChange".to_string(),
+            params: serde_json::json!({
+                "textDocument": VersionedTextDocumentIdentifier { uri, version },
+                "contentChanges": content_changes,
+            }),
+        }
+    }
+
+    /// Helper function to create a new `textDocument/didChange` notification message which
+    /// replaces the whole content of the document.
+    /// uri - The URI of the text document that changed.
+    /// version - The version of the document after the change.
+    /// text - The new content of the document.
+    pub fn new_did_change_full(uri: String, version: i32, text: String) -> Self {
+        Self::new_did_change(
+            uri,
+            version,
+            vec![TextDocumentContentChangeEvent { range: None, text }],
+        )
+    }
+
+    /// Helper function to create a new `textDocument/didClose` notification message.
+    /// uri - The URI of the text document that was closed.
+    pub fn new_did_close(uri: String) -> Self {
+        NotificationMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            method: "textDocument/didClose".to_string(),
+            params: serde_json::json!({
+                "textDocument": TextDocumentIdentifier { uri },
+            }),
+        }
+    }
+
+    /// Helper function to create a new `workspace/didRenameFiles` notification message.
+    /// renames - The files that were renamed.
+    pub fn new_did_rename_files(renames: Vec<FileRename>) -> Self {
+        NotificationMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            method: "workspace/didRenameFiles".to_string(),
+            params: serde_json::json!({ "files": renames }),
+        }
+    }
+
+    /// Helper function to create a new `workspace/didCreateFiles` notification message.
+    /// files - The files that were created.
+    pub fn new_did_create_files(files: Vec<FileCreate>) -> Self {
+        NotificationMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            method: "workspace/didCreateFiles".to_string(),
+            params: serde_json::json!({ "files": files }),
+        }
+    }
+
+    /// Helper function to create a new `workspace/didDeleteFiles` notification message.
+    /// files - The files that were deleted.
+    pub fn new_did_delete_files(files: Vec<FileDelete>) -> Self {
+        NotificationMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            method: "workspace/didDeleteFiles".to_string(),
+            params: serde_json::json!({ "files": files }),
+        }
+    }
+}
+
+impl ResponseMessage {
+    /// Helper function to create a successful response to a request sent by the server.
+    /// id - The ID of the request being answered.
+    /// result - The result of the request. Use `serde_json::Value::Null` for requests without one.
+    pub fn new_result(id: serde_json::Value, result: serde_json::Value) -> Self {
+        ResponseMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: Some(id),
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    /// Helper function to create an error response to a request sent by the server.
+    /// id - The ID of the request being answered.
+    /// error - The error to report back to the server.
+    pub fn new_error(id: serde_json::Value, error: ResponseError) -> Self {
+        ResponseMessage {
+            base_message: BaseMessage {
+                jsonrpc: "2.0".to_string(),
+            },
+            id: Some(id),
+            result: None,
+            error: Some(error),
+        }
+    }
+
+    pub fn handle_initialize(&self) -> Result<InitializeResult> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) => Ok(serde_json::from_value(res.clone())?),
+            None => bail!("No initialize result found."),
+        }
+    }
+
+    pub fn handle_shutdown(&self) -> Result<()> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        Ok(())
+    }
+
+    /// Parses the result of a `textDocument/semanticTokens/full` or `/range` request.
+    /// Servers return `null` when there are no tokens, which is treated as an empty set.
+    pub fn handle_semantic_tokens(&self) -> Result<SemanticTokens> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) => Ok(serde_json::from_value(res.clone())?),
+            None => Ok(SemanticTokens {
+                result_id: None,
+                data: Vec::new(),
+            }),
+        }
+    }
+
+    /// Parses the result of a `textDocument/semanticTokens/full/delta` request.
+    pub fn handle_semantic_tokens_delta(&self) -> Result<SemanticTokensDeltaResult> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) => Ok(serde_json::from_value(res.clone())?),
+            None => bail!("No semantic tokens found."),
+        }
+    }
+
+    pub fn handle_workspace_symbol_resolve(&self) -> Result<WorkspaceSymbol> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) => Ok(serde_json::from_value(res.clone())?),
+            None => bail!("No workspace symbol found."),
+        }
+    }
+
+    /// Parses the result of a request answered with a `WorkspaceEdit`, such as
+    /// `workspace/willRenameFiles`. Servers return `null` when there is nothing to edit.
+    pub fn handle_workspace_edit(&self) -> Result<Option<WorkspaceEdit>> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) => Ok(serde_json::from_value(res.clone())?),
+            None => Ok(None),
+        }
+    }
+
+    /// Parses the result of a `textDocument/diagnostic` request.
+    pub fn handle_document_diagnostic(&self) -> Result<DocumentDiagnosticReport> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) => Ok(serde_json::from_value(res.clone())?),
+            None => bail!("No diagnostic report found."),
+        }
+    }
+
+    /// Parses the result of a `workspace/diagnostic` request.
+    pub fn handle_workspace_diagnostic(&self) -> Result<WorkspaceDiagnosticReport> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) => Ok(serde_json::from_value(res.clone())?),
+            None => bail!("No workspace diagnostic report found."),
+        }
+    }
+
+    /// Parses the result of a `textDocument/hover` request.
+    /// Servers return `null` when there is nothing to show at the position.
+    pub fn handle_hover(&self) -> Result<Option<HoverResult>> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) => Ok(serde_json::from_value(res.clone())?),
+            None => Ok(None),
+        }
+    }
+
+    /// Parses the result of a `textDocument/documentSymbol` request.
+    /// `null` is returned as an empty `Nested` list.
+    pub fn handle_document_symbol(&self) -> Result<DocumentSymbolResponse> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(serde_json::Value::Null) | None => Ok(DocumentSymbolResponse::Nested(Vec::new())),
+            Some(res) => Ok(serde_json::from_value(res.clone())?),
+        }
+    }
+
+    /// Parses the result of a `textDocument/prepareCallHierarchy` request. There may be
+    /// several items, e.g. for overloads, and none when the position is not on a callable,
+    /// for which servers return `null`.
+    pub fn handle_prepare_call_hierarchy(&self) -> Result<Vec<CallHierarchyItem>> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(serde_json::Value::Null) | None => Ok(Vec::new()),
+            Some(res) => Ok(serde_json::from_value(res.clone())?),
+        }
+    }
+
+    /// Parses the result of a `callHierarchy/incomingCalls` request.
+    /// `null` is returned as an empty list.
+    pub fn handle_incoming_calls(&self) -> Result<Vec<CallHierarchyIncomingCall>> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(serde_json::Value::Null) | None => Ok(Vec::new()),
+            Some(res) => Ok(serde_json::from_value(res.clone())?),
+        }
+    }
+
+    /// Parses the result of a `callHierarchy/outgoingCalls` request.
+    /// `null` is returned as an empty list.
+    pub fn handle_outgoing_calls(&self) -> Result<Vec<CallHierarchyOutgoingCall>> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(serde_json::Value::Null) | None => Ok(Vec::new()),
+            Some(res) => Ok(serde_json::from_value(res.clone())?),
+        }
+    }
+
+    /// Parses the edits returned by a formatting request such as
+    /// `textDocument/onTypeFormatting`. `null` is returned as an empty list.
+    pub fn handle_formatting(&self) -> Result<Vec<TextEdit>> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(serde_json::Value::Null) | None => Ok(Vec::new()),
+            Some(res) => Ok(serde_json::from_value(res.clone())?),
+        }
+    }
+
+    /// Parses the result of a `textDocument/inlineValue` request. `null` is returned as
+    /// an empty list.
+    pub fn handle_inline_value(&self) -> Result<Vec<InlineValue>> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(serde_json::Value::Null) | None => Ok(Vec::new()),
+            Some(res) => Ok(serde_json::from_value(res.clone())?),
+        }
+    }
+
+    /// Parses the result of a `textDocument/signatureHelp` request.
+    /// Returns `None` if the position is not inside a call.
+    pub fn handle_signature_help(&self) -> Result<Option<SignatureHelp>> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(res) => Ok(serde_json::from_value(res.clone())?),
+            None => Ok(None),
+        }
+    }
+
+    /// Parses the result of a `textDocument/completion` request. A plain array of items is
+    /// returned as a complete list, and `itemDefaults` are merged into every item.
+    pub fn handle_completion(&self) -> Result<CompletionList> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        let mut list = match &self.result {
+            Some(res) if res.is_array() => CompletionList {
+                items: serde_json::from_value(res.clone())?,
+                ..CompletionList::default()
+            },
+            Some(res) => serde_json::from_value(res.clone())?,
+            None => CompletionList::default(),
+        };
+        list.merge_item_defaults();
+        Ok(list)
+    }
+
+    /// Parses the result of `textDocument/definition`. Location links are reduced to
+    /// their targets; use `handle_goto` to keep their origin selection range.
+    pub fn handle_definition(&self) -> Result<Vec<Location>> {
+        match self.handle_goto()? {
+            Some(response) => Ok(response.locations()),
+            None => bail!("No definition found."),
+        }
+    }
+
+    /// Parses the result of a goto request like `textDocument/definition`, keeping
+    /// location links intact. Returns `None` if the server found nothing.
+    pub fn handle_goto(&self) -> Result<Option<GotoResponse>> {
+        if self.error.is_some() {
+            bail!("Error from LSP server: {:?}", self.error);
+        };
+
+        match &self.result {
+            Some(serde_json::Value::Null) | None => Ok(None),
+            Some(res) => serde_json::from_value(res.clone())
+                .map(Some)
+                .map_err(|_| anyhow!("Failed to parse definition location(s) from response.")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_initialize_message() {
+        let process_id = std::process::id();
+        let expected_init_json = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "notification": 0,
+            "method": "initialize",
+            "params": {
+                "processId": process_id,
+                "clientInfo": {
+                    "name": "YourLSPClientName", // Customize this
+                    "version": "1.0.0" // Optional: Adjust as necessary
+                },
+                "rootUri": "file://path/to/root",
+                "capabilities": {
+                    "workspace": {
+                        "workspaceFolders": true,
+                        "didChangeConfiguration": {
+                            "dynamicRegistration": true
+                        },
+                        "workspaceEdit": {
+                            "documentChanges": true
+                        },
+                        "configuration": true
+                    },
+                    "textDocument": {
+                        "hover": {
+                            "contentFormat": ["plaintext"]
+                        },
+                        "completion": {
+                            "completionItem": {
+                                "snippetSupport": true // Set to false if your client does not support snippets
+                            }
+                        },
+                        "codeAction": {
+                            "codeActionLiteralSupport": {
+                                "codeActionKind": {
+                                    "valueSet": ["source.organizeImports", "refactor.rewrite", "refactor.extract"]
+                                }
+                            },
+                            "dataSupport": true,
+                            "resolveSupport": {
+                                "properties": ["edit"]
+                            }
+                        },
+                        "documentSymbol": {
+                            "hierarchicalDocumentSymbolSupport": true
+                        },
+                        "synchronization": {
+                            "dynamicRegistration": false,
+                            "willSave": true,
+                            "willSaveWaitUntil": false,
+                            "didSave": true
+                        }
+                    },
+                    "window": {
+                        "workDoneProgress": true
+                    }
+                },
+                "workspaceFolders": [{
+                    "uri": "file://path/to/workspace",
+                    "name": "file://path/to/workspace" ,
+                }]
+            }
+        });
+
+        let init_params = RequestMessage::new_initialize(
+            1,
+            Some(process_id),
+            "file://path/to/root".to_string(),
+            "YourLSPClientName".to_string(),
+            Some("1.0.0".to_string()),
+            vec![WorkspaceFolder {
+                uri: "file://path/to/workspace".to_string(),
+                name: "file://path/to/workspace".to_string(),
+            }],
+        );
+
+        // Check that the JSON serialization is correct
+        let init_params_json = serde_json::to_value(init_params).unwrap();
+        assert_eq!(expected_init_json, init_params_json);
+
+        // A missing process ID must still be sent, as `null`
+        let expected_init_json_clone = expected_init_json.clone();
+        let mut expected_null_json = expected_init_json;
+        expected_null_json["params"]["processId"] = serde_json::Value::Null;
+        let null_params = RequestMessage::new_initialize(
+            1,
+            None,
+            "file://path/to/root".to_string(),
+            "YourLSPClientName".to_string(),
+            Some("1.0.0".to_string()),
+            vec![WorkspaceFolder {
+                uri: "file://path/to/workspace".to_string(),
+                name: "file://path/to/workspace".to_string(),
+            }],
+        );
+        assert_eq!(
+            expected_null_json,
+            serde_json::to_value(null_params).unwrap()
+        );
+
+        // File operations are only advertised when requested
+        let mut expected_file_ops_json = expected_init_json_clone;
+        expected_file_ops_json["params"]["capabilities"]["workspace"]["fileOperations"] = json!({
+            "dynamicRegistration": false,
+            "didCreate": false,
+            "willCreate": false,
+            "didRename": true,
+            "willRename": true,
+            "didDelete": false,
+            "willDelete": false,
+        });
+        let file_ops_params = RequestMessage::new_initialize_with_capabilities(
+            1,
+            Some(process_id),
+            "file://path/to/root".to_string(),
+            "YourLSPClientName".to_string(),
+            Some("1.0.0".to_string()),
+            vec![WorkspaceFolder {
+                uri: "file://path/to/workspace".to_string(),
+                name: "file://path/to/workspace".to_string(),
+            }],
+            ClientCapabilities::default().with_file_operations(FileOperations {
+                did_rename: true,
+                will_rename: true,
+                ..Default::default()
+            }),
+        );
+        assert_eq!(
+            expected_file_ops_json,
+            serde_json::to_value(file_ops_params).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_initialize_raw() {
+        let params = json!({
+            "processId": null,
+            "rootUri": "file:///repo",
+            "capabilities": { "experimental": { "futureFeature": { "enabled": true } } }
+        });
+        let request = RequestMessage::new_initialize_raw(1, params.clone());
+        assert_eq!(
+            serde_json::to_value(request).unwrap(),
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "notification": 0,
+                "method": "initialize",
+                "params": params
+            })
+        );
+    }
+
+    #[test]
+    fn test_initialize_workspace_folder_order() {
+        let folders = ["file:///repo/services/api", "file:///repo", "file:///repo"]
+            .iter()
+            .enumerate()
+            .map(|(index, uri)| WorkspaceFolder::new(*uri, format!("folder-{}", index)).unwrap())
+            .collect();
+        let request = RequestMessage::new_initialize(
+            1,
+            None,
+            "file:///repo".to_string(),
+            "test".to_string(),
+            None,
+            folders,
+        );
+
+        // The first folder stays first and duplicates are kept
+        assert_eq!(
+            serde_json::to_value(request).unwrap()["params"]["workspaceFolders"],
+            json!([
+                { "uri": "file:///repo/services/api", "name": "folder-0" },
+                { "uri": "file:///repo", "name": "folder-1" },
+                { "uri": "file:///repo", "name": "folder-2" },
+            ])
+        );
+        assert!(WorkspaceFolder::new("/repo", "repo").is_err());
+    }
+
+    #[test]
+    fn test_initialized_notification() {
+        let expected_initialized_json = json!({
+            "jsonrpc": "2.0",
+            "method": "initialized",
+            "params": {}
+        });
+
+        let initialized_notification = NotificationMessage::new_initialized();
+        let initialized_notification_json = serde_json::to_value(initialized_notification).unwrap();
+        assert_eq!(expected_initialized_json, initialized_notification_json);
+    }
+
+    #[test]
+    fn test_get_definition() {
+        let expected_get_definition_json = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "notification": 0,
+            "method": "textDocument/definition",
+            "params": {
+                "textDocument": {
+                    "uri": "file://path/to/code/main.go"
+                },
+                "position": {
+                    "line": 1,
+                    "character": 2
+                }
+            }
+        });
+
+        let get_definition = RequestMessage::new_get_definition(
+            1,
+            "file://path/to/code/main.go".to_string(),
+            Position {
+                line: 1,
+                character: 2,
+            },
+        );
+
+        let get_definition_json = serde_json::to_value(get_definition).unwrap();
+        assert_eq!(expected_get_definition_json, get_definition_json);
+    }
+
+    #[test]
+    fn test_response_error_code() {
+        let response: ResponseMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "error": {
+                "code": -32601,
+                "message": "method not found"
+            }
+        }))
+        .unwrap();
+
+        let error = response.error.unwrap();
+        assert_eq!(error.code, LspErrorCode::MethodNotFound);
+        assert_eq!(error.message, "method not found");
+
+        let unknown: ResponseError =
+            serde_json::from_value(json!({ "code": -1, "message": "custom" })).unwrap();
+        assert_eq!(unknown.code, LspErrorCode::Other(-1));
+        assert_eq!(
+            serde_json::to_value(&unknown).unwrap(),
+            json!({ "code": -1, "message": "custom" })
+        );
+    }
+
+    #[test]
+    fn test_telemetry_event_notification() {
+        let notification: NotificationMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "method": "telemetry/event",
+            "params": { "event": "indexed", "files": 42 }
+        }))
+        .unwrap();
+
+        match ServerNotification::from(notification) {
+            ServerNotification::TelemetryEvent(TelemetryEventParams(params)) => {
+                assert_eq!(params, json!({ "event": "indexed", "files": 42 }));
+            }
+            other => panic!("Expected telemetry event, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_positional_params() {
+        let raw = RequestMessage::new_raw(4, "custom/sum".to_string(), json!([1, 2, 3]));
+        let raw_json = serde_json::to_string(&raw).unwrap();
+        let round_trip: RequestMessage = serde_json::from_str(&raw_json).unwrap();
+        assert_eq!(round_trip.method, "custom/sum");
+        assert_eq!(round_trip.params, json!([1, 2, 3]));
+
+        let execute = RequestMessage::new_execute_command(
+            5,
+            "custom.sum".to_string(),
+            vec![json!(1), json!(2), json!(3)],
+            None,
+        );
+        assert_eq!(
+            serde_json::to_value(execute).unwrap()["params"],
+            json!({ "command": "custom.sum", "arguments": [1, 2, 3] })
+        );
+
+        let execute = RequestMessage::new_execute_command(
+            6,
+            "project.reindex".to_string(),
+            vec![],
+            Some(ProgressToken::String("reindex-1".to_string())),
+        );
+        assert_eq!(execute.params["workDoneToken"], json!("reindex-1"));
+    }
+
+    #[test]
+    fn test_did_open_notification() {
+        let did_open = NotificationMessage::new_did_open(
+            "file://path/to/code/main.go".to_string(),
+            "go".to_string(),
+            1,
+            "package main".to_string(),
+        );
+
+        assert_eq!(
+            serde_json::to_value(did_open).unwrap(),
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didOpen",
+                "params": {
+                    "textDocument": {
+                        "uri": "file://path/to/code/main.go",
+                        "languageId": "go",
+                        "version": 1,
+                        "text": "package main"
+                    }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_initialize_result_server_info() {
+        let response: ResponseMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "result": {
+                "capabilities": { "hoverProvider": true },
+                "serverInfo": { "name": "gopls", "version": "v0.15.2" }
+            }
+        }))
+        .unwrap();
+        let result = response.handle_initialize().unwrap();
+        assert_eq!(
+            result.server_info,
+            Some(ServerInfo {
+                name: "gopls".to_string(),
+                version: Some("v0.15.2".to_string()),
+            })
+        );
+        // Capabilities that are not typed are kept
+        assert_eq!(result.capabilities.other["hoverProvider"], json!(true));
+        assert_eq!(result.capabilities.completion_provider, None);
+
+        let response: ResponseMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "result": { "capabilities": {} }
+        }))
+        .unwrap();
+        assert_eq!(response.handle_initialize().unwrap().server_info, None);
+    }
+
+    #[test]
+    fn test_server_capabilities_characters() {
+        let result: InitializeResult = serde_json::from_value(json!({
+            "capabilities": {
+                "completionProvider": { "triggerCharacters": ["."], "allCommitCharacters": [";"] },
+                "signatureHelpProvider": { "triggerCharacters": ["(", ","] },
+            }
+        }))
+        .unwrap();
+        let capabilities = result.capabilities;
+        assert_eq!(capabilities.completion_trigger_characters(), ["."]);
+        assert_eq!(capabilities.completion_commit_characters(), [";"]);
+        assert_eq!(capabilities.signature_help_trigger_characters(), ["(", ","]);
+
+        let capabilities = ServerCapabilities::default();
+        assert!(capabilities.completion_trigger_characters().is_empty());
+        assert!(capabilities.signature_help_trigger_characters().is_empty());
+    }
+
+    #[test]
+    fn test_text_document_sync_kind() {
+        let sync_kind = |capabilities: serde_json::Value| {
+            serde_json::from_value::<ServerCapabilities>(capabilities)
+                .unwrap()
+                .text_document_sync_kind()
+        };
+        assert_eq!(
+            sync_kind(json!({ "textDocumentSync": 2 })),
+            TextDocumentSyncKind::Incremental
+        );
+        assert_eq!(
+            sync_kind(json!({ "textDocumentSync": { "openClose": true, "change": 1 } })),
+            TextDocumentSyncKind::Full
+        );
+        assert_eq!(
+            sync_kind(json!({ "textDocumentSync": { "openClose": true } })),
+            TextDocumentSyncKind::None
+        );
+        assert_eq!(sync_kind(json!({})), TextDocumentSyncKind::None);
+    }
+
+    #[test]
+    fn test_handle_goto_location_links() {
+        let response: ResponseMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "result": [{
+                "originSelectionRange": {
+                    "start": { "line": 4, "character": 13 },
+                    "end": { "line": 4, "character": 16 }
                 },
-                completion: Completion {
-                    completion_item: CompletionItem {
-                        snippet_support: true,
-                    },
+                "targetUri": "file://path/to/code/main.go",
+                "targetRange": {
+                    "start": { "line": 2, "character": 0 },
+                    "end": { "line": 2, "character": 13 }
+                },
+                "targetSelectionRange": {
+                    "start": { "line": 2, "character": 5 },
+                    "end": { "line": 2, "character": 8 }
+                }
+            }]
+        }))
+        .unwrap();
+
+        let Some(GotoResponse::Links(links)) = response.handle_goto().unwrap() else {
+            panic!("expected location links");
+        };
+        assert_eq!(
+            links[0].origin_selection_range,
+            Some(Range::new(4, 13, 4, 16))
+        );
+        assert_eq!(
+            response.handle_definition().unwrap(),
+            vec![Location {
+                uri: "file://path/to/code/main.go".to_string(),
+                range: Range::new(2, 5, 2, 8),
+            }]
+        );
+
+        let response = ResponseMessage::new_result(json!(1), json!(null));
+        assert_eq!(response.handle_goto().unwrap(), None);
+        assert!(response.handle_definition().is_err());
+    }
+
+    #[test]
+    fn test_partial_result_token() {
+        let request = RequestMessage::new_get_definition(
+            1,
+            "file://path/to/code/main.go".to_string(),
+            Position::new(1, 2),
+        )
+        .with_partial_result_token(ProgressToken::String("defs".to_string()));
+
+        assert_eq!(
+            serde_json::to_value(request).unwrap()["params"]["partialResultToken"],
+            json!("defs")
+        );
+    }
+
+    #[test]
+    fn test_semantic_tokens_range() {
+        let range = Range {
+            start: Position::new(10, 0),
+            end: Position::new(20, 0),
+        };
+        let request = RequestMessage::new_semantic_tokens_range(
+            1,
+            "file://path/to/code/main.go".to_string(),
+            range,
+        );
+        assert_eq!(
+            serde_json::to_value(request).unwrap()["params"],
+            json!({
+                "textDocument": { "uri": "file://path/to/code/main.go" },
+                "range": {
+                    "start": { "line": 10, "character": 0 },
+                    "end": { "line": 20, "character": 0 }
+                }
+            })
+        );
+
+        let response: ResponseMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "result": { "data": [10, 4, 3, 0, 1, 0, 5, 6, 1, 0, 2, 2, 4, 0, 0] }
+        }))
+        .unwrap();
+        let legend = SemanticTokensLegend {
+            token_types: vec!["function".to_string(), "variable".to_string()],
+            token_modifiers: vec!["declaration".to_string()],
+        };
+        let tokens = response
+            .handle_semantic_tokens()
+            .unwrap()
+            .decode(&legend)
+            .unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                SemanticToken {
+                    line: 10,
+                    start: 4,
+                    length: 3,
+                    token_type: "function".to_string(),
+                    token_modifiers: vec!["declaration".to_string()],
+                },
+                SemanticToken {
+                    line: 10,
+                    start: 9,
+                    length: 6,
+                    token_type: "variable".to_string(),
+                    token_modifiers: vec![],
+                },
+                SemanticToken {
+                    line: 12,
+                    start: 2,
+                    length: 4,
+                    token_type: "function".to_string(),
+                    token_modifiers: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_semantic_tokens_delta() {
+        let request = RequestMessage::new_semantic_tokens_delta(
+            2,
+            "file://path/to/code/main.go".to_string(),
+            "1".to_string(),
+        );
+        assert_eq!(
+            serde_json::to_value(request).unwrap()["params"]["previousResultId"],
+            json!("1")
+        );
+
+        let previous = SemanticTokens {
+            result_id: Some("1".to_string()),
+            data: vec![2, 5, 3, 0, 3, 0, 5, 4, 1, 0, 3, 2, 7, 2, 0],
+        };
+        let response: ResponseMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "result": {
+                "resultId": "2",
+                "edits": [
+                    { "start": 10, "deleteCount": 1, "data": [4] },
+                    { "start": 0, "deleteCount": 0, "data": [0, 1, 1, 1, 0] }
+                ]
+            }
+        }))
+        .unwrap();
+
+        let delta = match response.handle_semantic_tokens_delta().unwrap() {
+            SemanticTokensDeltaResult::Delta(delta) => delta,
+            other => panic!("Expected a delta, got {:?}", other),
+        };
+        let current = delta.apply(&previous).unwrap();
+        assert_eq!(current.result_id, Some("2".to_string()));
+        assert_eq!(
+            current.data,
+            vec![0, 1, 1, 1, 0, 2, 5, 3, 0, 3, 0, 5, 4, 1, 0, 4, 2, 7, 2, 0]
+        );
+    }
+
+    #[test]
+    fn test_workspace_symbol_resolve() {
+        let symbol = WorkspaceSymbol {
+            name: "main".to_string(),
+            kind: 12,
+            tags: None,
+            container_name: None,
+            location: WorkspaceSymbolLocation::Uri {
+                uri: "file://path/to/code/main.go".to_string(),
+            },
+            data: Some(json!({ "id": 3 })),
+        };
+        let request = RequestMessage::new_workspace_symbol_resolve(1, symbol);
+        assert_eq!(
+            serde_json::to_value(request).unwrap()["params"],
+            json!({
+                "name": "main",
+                "kind": 12,
+                "location": { "uri": "file://path/to/code/main.go" },
+                "data": { "id": 3 }
+            })
+        );
+
+        let response: ResponseMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "result": {
+                "name": "main",
+                "kind": 12,
+                "location": {
+                    "uri": "file://path/to/code/main.go",
+                    "range": {
+                        "start": { "line": 4, "character": 5 },
+                        "end": { "line": 4, "character": 9 }
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        let resolved = response.handle_workspace_symbol_resolve().unwrap();
+        assert_eq!(
+            resolved.location,
+            WorkspaceSymbolLocation::Location(Location {
+                uri: "file://path/to/code/main.go".to_string(),
+                range: Range {
+                    start: Position::new(4, 5),
+                    end: Position::new(4, 9),
                 },
-                code_action: CodeAction {
-                    code_action_literal_support: CodeActionLiteralSupport {
-                        code_action_kind: CodeActionKind {
-                            value_set: vec![
-                                "source.organizeImports".to_string(),
-                                "refactor.rewrite".to_string(),
-                                "refactor.extract".to_string(),
-                            ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_cancel_request_notification() {
+        let cancel = NotificationMessage::new_cancel_request(RequestId::String("cfg".to_string()));
+        let cancel_json = serde_json::to_value(&cancel).unwrap();
+        assert_eq!(
+            cancel_json,
+            json!({ "jsonrpc": "2.0", "method": "$/cancelRequest", "params": { "id": "cfg" } })
+        );
+
+        match ServerNotification::from(cancel) {
+            ServerNotification::CancelRequest(params) => {
+                assert_eq!(params.id, RequestId::String("cfg".to_string()))
+            }
+            other => panic!("Expected a cancel request, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_initialize_without_client_version() {
+        let init_params = RequestMessage::new_initialize(
+            1,
+            Some(1234),
+            "file://path/to/root".to_string(),
+            "YourLSPClientName".to_string(),
+            None,
+            vec![],
+        );
+
+        let init_params_json = serde_json::to_value(init_params).unwrap();
+        assert_eq!(
+            init_params_json["params"]["clientInfo"],
+            json!({ "name": "YourLSPClientName" })
+        );
+
+        let init_params = RequestMessage::new_initialize_from_params(
+            1,
+            InitializeParams {
+                process_id: Some(1234),
+                root_uri: "file://path/to/root".to_string(),
+                client_info: None,
+                capabilities: ClientCapabilities::default(),
+                workspace_folders: None,
+            },
+        );
+        let init_params_json = serde_json::to_value(init_params).unwrap();
+        assert!(init_params_json["params"].get("clientInfo").is_none());
+        assert_eq!(init_params_json["params"]["rootUri"], "file://path/to/root");
+    }
+
+    #[test]
+    fn test_rename_files() {
+        let renames = vec![FileRename {
+            old_uri: "file:///code/util.go".to_string(),
+            new_uri: "file:///code/strings/util.go".to_string(),
+        }];
+        let files = json!({
+            "files": [{
+                "oldUri": "file:///code/util.go",
+                "newUri": "file:///code/strings/util.go",
+            }]
+        });
+
+        let will_rename = RequestMessage::new_will_rename_files(4, renames.clone());
+        assert_eq!(will_rename.method, "workspace/willRenameFiles");
+        assert_eq!(will_rename.params, files);
+
+        let did_rename = NotificationMessage::new_did_rename_files(renames);
+        assert_eq!(did_rename.method, "workspace/didRenameFiles");
+        assert_eq!(did_rename.params, files);
+
+        let response: ResponseMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 4,
+            "result": {
+                "documentChanges": [
+                    {
+                        "textDocument": { "uri": "file:///code/main.go", "version": 3 },
+                        "edits": [{
+                            "range": {
+                                "start": { "line": 2, "character": 8 },
+                                "end": { "line": 2, "character": 20 },
+                            },
+                            "newText": "\"example.com/strings\"",
+                        }],
+                    },
+                    { "kind": "rename", "oldUri": "file:///code/a.go", "newUri": "file:///code/b.go" },
+                ]
+            }
+        }))
+        .unwrap();
+        let edit = response.handle_workspace_edit().unwrap().unwrap();
+        let changes = edit.document_changes.unwrap();
+        match &changes[0] {
+            DocumentChange::Edit(edit) => {
+                assert_eq!(edit.text_document.version, Some(3));
+                assert_eq!(edit.edits[0].range.start, Position::new(2, 8));
+            }
+            other => panic!("Expected a text document edit, got {:?}", other),
+        }
+        assert_eq!(
+            changes[1],
+            DocumentChange::Operation(ResourceOperation::Rename {
+                old_uri: "file:///code/a.go".to_string(),
+                new_uri: "file:///code/b.go".to_string(),
+            })
+        );
+
+        let empty: ResponseMessage =
+            serde_json::from_value(json!({ "jsonrpc": "2.0", "id": 4, "result": null })).unwrap();
+        assert_eq!(empty.handle_workspace_edit().unwrap(), None);
+    }
+
+    #[test]
+    fn test_create_files() {
+        let files = vec![FileCreate {
+            uri: "file:///code/handler.go".to_string(),
+        }];
+
+        let will_create = RequestMessage::new_will_create_files(5, files.clone());
+        assert_eq!(will_create.method, "workspace/willCreateFiles");
+        assert_eq!(
+            will_create.params,
+            json!({ "files": [{ "uri": "file:///code/handler.go" }] })
+        );
+
+        let did_create = NotificationMessage::new_did_create_files(files);
+        assert_eq!(did_create.method, "workspace/didCreateFiles");
+
+        let response: ResponseMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 5,
+            "result": {
+                "changes": {
+                    "file:///code/handler.go": [{
+                        "range": {
+                            "start": { "line": 0, "character": 0 },
+                            "end": { "line": 0, "character": 0 },
                         },
+                        "newText": "package code\n",
+                    }]
+                }
+            }
+        }))
+        .unwrap();
+        let edit = response.handle_workspace_edit().unwrap().unwrap();
+        let changes = edit.changes.unwrap();
+        assert_eq!(
+            changes["file:///code/handler.go"][0].new_text,
+            "package code\n"
+        );
+    }
+
+    #[test]
+    fn test_delete_files() {
+        let files = vec![FileDelete {
+            uri: "file:///code/legacy.go".to_string(),
+        }];
+
+        let will_delete = RequestMessage::new_will_delete_files(6, files.clone());
+        assert_eq!(will_delete.method, "workspace/willDeleteFiles");
+        assert_eq!(
+            will_delete.params,
+            json!({ "files": [{ "uri": "file:///code/legacy.go" }] })
+        );
+
+        let did_delete = NotificationMessage::new_did_delete_files(files);
+        assert_eq!(did_delete.method, "workspace/didDeleteFiles");
+        assert_eq!(did_delete.params, will_delete.params);
+    }
+
+    #[test]
+    fn test_document_diagnostic() {
+        let request = RequestMessage::new_document_diagnostic(
+            7,
+            "file:///code/main.go".to_string(),
+            Some("r1".to_string()),
+        );
+        assert_eq!(request.method, "textDocument/diagnostic");
+        assert_eq!(
+            request.params,
+            json!({ "textDocument": { "uri": "file:///code/main.go" }, "previousResultId": "r1" })
+        );
+
+        let full: ResponseMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 7,
+            "result": {
+                "kind": "full",
+                "resultId": "r2",
+                "items": [{
+                    "range": {
+                        "start": { "line": 3, "character": 1 },
+                        "end": { "line": 3, "character": 4 },
                     },
-                },
+                    "severity": 1,
+                    "code": "UnusedVar",
+                    "source": "compiler",
+                    "message": "declared and not used: foo",
+                }]
+            }
+        }))
+        .unwrap();
+        match full.handle_document_diagnostic().unwrap() {
+            DocumentDiagnosticReport::Full { result_id, items } => {
+                assert_eq!(result_id.as_deref(), Some("r2"));
+                assert_eq!(items[0].severity, Some(DiagnosticSeverity::Error));
+                assert_eq!(items[0].message, "declared and not used: foo");
+            }
+            other => panic!("Expected a full report, got {:?}", other),
+        }
+
+        let unchanged: ResponseMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 8,
+            "result": { "kind": "unchanged", "resultId": "r2" }
+        }))
+        .unwrap();
+        assert_eq!(
+            unchanged.handle_document_diagnostic().unwrap(),
+            DocumentDiagnosticReport::Unchanged {
+                result_id: "r2".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_workspace_diagnostic() {
+        let request = RequestMessage::new_workspace_diagnostic(
+            9,
+            vec![PreviousResultId {
+                uri: "file:///code/main.go".to_string(),
+                value: "r1".to_string(),
+            }],
+        );
+        assert_eq!(request.method, "workspace/diagnostic");
+        assert_eq!(
+            request.params,
+            json!({ "previousResultIds": [{ "uri": "file:///code/main.go", "value": "r1" }] })
+        );
+
+        let response: ResponseMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 9,
+            "result": {
+                "items": [
+                    { "uri": "file:///code/main.go", "version": 2, "kind": "unchanged", "resultId": "r1" },
+                    {
+                        "uri": "file:///code/util.go",
+                        "version": null,
+                        "kind": "full",
+                        "items": [{
+                            "range": {
+                                "start": { "line": 0, "character": 0 },
+                                "end": { "line": 0, "character": 1 },
+                            },
+                            "message": "missing package clause",
+                        }]
+                    },
+                ]
+            }
+        }))
+        .unwrap();
+        let report = response.handle_workspace_diagnostic().unwrap();
+        assert_eq!(report.items.len(), 2);
+        assert_eq!(report.items[0].version, Some(2));
+        assert_eq!(
+            report.items[0].report,
+            DocumentDiagnosticReport::Unchanged {
+                result_id: "r1".to_string()
+            }
+        );
+        match &report.items[1].report {
+            DocumentDiagnosticReport::Full { result_id, items } => {
+                assert_eq!(*result_id, None);
+                assert_eq!(items[0].message, "missing package clause");
+            }
+            other => panic!("Expected a full report, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_completion_context() {
+        let triggered = RequestMessage::new_completion(
+            3,
+            "file:///code/main.go".to_string(),
+            Position::new(4, 8),
+            Some(CompletionContext {
+                trigger_kind: CompletionTriggerKind::TriggerCharacter,
+                trigger_character: Some(".".to_string()),
+            }),
+        );
+        assert_eq!(triggered.method, "textDocument/completion");
+        assert_eq!(
+            triggered.params,
+            json!({
+                "textDocument": { "uri": "file:///code/main.go" },
+                "position": { "line": 4, "character": 8 },
+                "context": { "triggerKind": 2, "triggerCharacter": "." },
+            })
+        );
+
+        let invoked = RequestMessage::new_completion(
+            4,
+            "file:///code/main.go".to_string(),
+            Position::new(4, 8),
+            Some(CompletionContext {
+                trigger_kind: CompletionTriggerKind::Invoked,
+                trigger_character: None,
             }),
+        );
+        assert_eq!(invoked.params["context"], json!({ "triggerKind": 1 }));
+
+        let without_context = RequestMessage::new_completion(
+            5,
+            "file:///code/main.go".to_string(),
+            Position::new(4, 8),
+            None,
+        );
+        assert!(without_context.params.get("context").is_none());
+    }
+
+    #[test]
+    fn test_completion_item_defaults() {
+        let response: ResponseMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "result": {
+                "isIncomplete": false,
+                "itemDefaults": {
+                    "commitCharacters": ["("],
+                    "editRange": {
+                        "start": { "line": 4, "character": 5 },
+                        "end": { "line": 4, "character": 8 },
+                    },
+                    "insertTextFormat": 2,
+                    "data": { "package": "fmt" },
+                },
+                "items": [
+                    { "label": "Println", "textEditText": "Println($0)" },
+                    { "label": "Printf", "insertTextFormat": 1, "data": { "package": "log" } },
+                ]
+            }
+        }))
+        .unwrap();
+
+        let list = response.handle_completion().unwrap();
+        let range = Range {
+            start: Position::new(4, 5),
+            end: Position::new(4, 8),
+        };
+        assert_eq!(
+            list.items[0],
+            CompletionItem {
+                label: "Println".to_string(),
+                insert_text_format: Some(2),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit {
+                    range: range.clone(),
+                    new_text: "Println($0)".to_string(),
+                })),
+                text_edit_text: Some("Println($0)".to_string()),
+                commit_characters: Some(vec!["(".to_string()]),
+                data: Some(json!({ "package": "fmt" })),
+                ..CompletionItem::default()
+            }
+        );
+        assert_eq!(list.items[1].insert_text_format, Some(1));
+        assert_eq!(list.items[1].data, Some(json!({ "package": "log" })));
+        assert_eq!(
+            list.items[1].text_edit,
+            Some(CompletionTextEdit::Edit(TextEdit {
+                range,
+                new_text: "Printf".to_string(),
+            }))
+        );
+
+        let array: ResponseMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 4,
+            "result": [{ "label": "main" }]
+        }))
+        .unwrap();
+        let list = array.handle_completion().unwrap();
+        assert!(!list.is_incomplete);
+        assert_eq!(list.items[0].label, "main");
+    }
+
+    #[test]
+    fn test_call_hierarchy() {
+        let item = |detail: &str, line: u32| {
+            let range = Range {
+                start: Position::new(line, 0),
+                end: Position::new(line + 2, 1),
+            };
+            json!({
+                "name": "Add",
+                "kind": 6,
+                "detail": detail,
+                "uri": "file:///code/math.cs",
+                "range": range,
+                "selectionRange": range,
+                "data": { "overload": detail },
+            })
+        };
+        let response: ResponseMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "result": [item("Add(int, int)", 3), item("Add(double, double)", 7)]
+        }))
+        .unwrap();
+        let items = response.handle_prepare_call_hierarchy().unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].detail.as_deref(), Some("Add(double, double)"));
+
+        // The chosen overload is sent back unchanged, including its data
+        let incoming = RequestMessage::new_incoming_calls(2, items[1].clone());
+        assert_eq!(incoming.method, "callHierarchy/incomingCalls");
+        assert_eq!(incoming.params["item"], item("Add(double, double)", 7));
+        let outgoing = RequestMessage::new_outgoing_calls(3, items[0].clone());
+        assert_eq!(outgoing.params["item"], item("Add(int, int)", 3));
+
+        let response: ResponseMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "result": [{
+                "from": item("Sum(double[])", 12),
+                "fromRanges": [{ "start": { "line": 13, "character": 8 }, "end": { "line": 13, "character": 11 } }],
+            }]
+        }))
+        .unwrap();
+        let calls = response.handle_incoming_calls().unwrap();
+        assert_eq!(calls[0].from.detail.as_deref(), Some("Sum(double[])"));
+        assert_eq!(calls[0].from_ranges[0].start, Position::new(13, 8));
+
+        // Not on a callable
+        let none = ResponseMessage::new_result(json!(1), serde_json::Value::Null);
+        assert!(none.handle_prepare_call_hierarchy().unwrap().is_empty());
+        assert!(none.handle_outgoing_calls().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_on_type_formatting() {
+        let options = FormattingOptions {
+            trim_trailing_whitespace: Some(true),
+            ..FormattingOptions::new(4, true)
+        };
+        let request = RequestMessage::new_on_type_formatting(
+            1,
+            "file:///code/main.go".to_string(),
+            Position::new(3, 1),
+            "}".to_string(),
+            options,
+        );
+        assert_eq!(request.method, "textDocument/onTypeFormatting");
+        assert_eq!(request.params["ch"], json!("}"));
+        assert_eq!(
+            request.params["options"],
+            json!({ "tabSize": 4, "insertSpaces": true, "trimTrailingWhitespace": true })
+        );
+
+        let response: ResponseMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "result": [{
+                "range": { "start": { "line": 3, "character": 0 }, "end": { "line": 3, "character": 4 } },
+                "newText": "",
+            }]
+        }))
+        .unwrap();
+        let edits = response.handle_formatting().unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].range.end, Position::new(3, 4));
+
+        let empty = ResponseMessage::new_result(json!(1), serde_json::Value::Null);
+        assert!(empty.handle_formatting().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_inline_value() {
+        let range = |line| Range {
+            start: Position::new(line, 4),
+            end: Position::new(line, 9),
+        };
+        let request = RequestMessage::new_inline_value(
+            1,
+            "file:///code/main.go".to_string(),
+            range(0),
+            InlineValueContext {
+                frame_id: 3,
+                stopped_location: range(7),
+            },
+        );
+        assert_eq!(request.method, "textDocument/inlineValue");
+        assert_eq!(request.params["context"]["frameId"], json!(3));
+        assert_eq!(
+            request.params["context"]["stoppedLocation"]["start"]["line"],
+            json!(7)
+        );
+
+        let response: ResponseMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "result": [
+                { "range": range(1), "text": "x = 1" },
+                { "range": range(2), "variableName": "count", "caseSensitiveLookup": true },
+                { "range": range(3), "expression": "len(items)" },
+                { "range": range(4) },
+            ]
+        }))
+        .unwrap();
+        assert_eq!(
+            response.handle_inline_value().unwrap(),
+            vec![
+                InlineValue::Text {
+                    range: range(1),
+                    text: "x = 1".to_string(),
+                },
+                InlineValue::VariableLookup {
+                    range: range(2),
+                    variable_name: Some("count".to_string()),
+                    case_sensitive_lookup: true,
+                },
+                InlineValue::EvaluatableExpression {
+                    range: range(3),
+                    expression: Some("len(items)".to_string()),
+                },
+                InlineValue::EvaluatableExpression {
+                    range: range(4),
+                    expression: None,
+                },
+            ]
+        );
+
+        let empty = ResponseMessage::new_result(json!(1), serde_json::Value::Null);
+        assert!(empty.handle_inline_value().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_documentation() {
+        let response: ResponseMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "result": {
+                "signatures": [{
+                    "label": "func Println(a ...any) (n int, err error)",
+                    "documentation": { "kind": "markdown", "value": "Println formats using the *default* formats." },
+                    "parameters": [{ "label": [13, 21], "documentation": "the values to print" }],
+                }],
+                "activeSignature": 0,
+                "activeParameter": 0,
+            }
+        }))
+        .unwrap();
+        let help = response.handle_signature_help().unwrap().unwrap();
+        let signature = &help.signatures[0];
+        assert_eq!(
+            signature.documentation.as_ref().unwrap().as_plain_text(),
+            "Println formats using the *default* formats."
+        );
+        let parameter = &signature.parameters.as_ref().unwrap()[0];
+        assert_eq!(parameter.label, ParameterLabel::Offsets([13, 21]));
+        assert_eq!(
+            parameter.documentation.as_ref().unwrap().as_plain_text(),
+            "the values to print"
+        );
+
+        let item: CompletionItem = serde_json::from_value(json!({
+            "label": "Println",
+            "documentation": { "kind": "plaintext", "value": "Println formats..." },
+        }))
+        .unwrap();
+        assert_eq!(
+            item.documentation.unwrap().as_plain_text(),
+            "Println formats..."
+        );
+
+        let hover: HoverResult = serde_json::from_value(json!({
+            "contents": [
+                { "language": "go", "value": "func Println(a ...any) (n int, err error)" },
+                "Println formats using the default formats.",
+            ],
+        }))
+        .unwrap();
+        assert_eq!(
+            hover.contents.as_plain_text(),
+            "func Println(a ...any) (n int, err error)\n\nPrintln formats using the default formats."
+        );
+        let hover: HoverResult = serde_json::from_value(json!({
+            "contents": { "kind": "markdown", "value": "Println formats..." },
+        }))
+        .unwrap();
+        assert_eq!(hover.contents.as_plain_text(), "Println formats...");
+
+        let request = RequestMessage::new_signature_help(
+            2,
+            "file:///code/main.go".into(),
+            Position::new(3, 15),
+        );
+        assert_eq!(request.method, "textDocument/signatureHelp");
+    }
+
+    #[test]
+    fn test_completion_continuation() {
+        let uri = "file:///code/main.go".to_string();
+        let incomplete = CompletionList {
+            is_incomplete: true,
+            item_defaults: None,
+            items: vec![],
+        };
+        let request = RequestMessage::new_completion_continuation(
+            2,
+            uri.clone(),
+            Position::new(4, 9),
+            &incomplete,
+        )
+        .unwrap();
+        assert_eq!(request.method, "textDocument/completion");
+        assert_eq!(
+            request.params["position"],
+            json!({ "line": 4, "character": 9 })
+        );
+        assert_eq!(request.params["context"], json!({ "triggerKind": 3 }));
+
+        let complete = CompletionList {
+            is_incomplete: false,
+            ..incomplete
         };
+        assert!(RequestMessage::new_completion_continuation(
+            3,
+            uri,
+            Position::new(4, 10),
+            &complete
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn test_completion_insert_replace_edit() {
+        let response: ResponseMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "result": [
+                {
+                    "label": "Println",
+                    "insertTextMode": 2,
+                    "textEdit": {
+                        "newText": "Println",
+                        "insert": {
+                            "start": { "line": 4, "character": 5 },
+                            "end": { "line": 4, "character": 7 },
+                        },
+                        "replace": {
+                            "start": { "line": 4, "character": 5 },
+                            "end": { "line": 4, "character": 12 },
+                        },
+                    },
+                },
+                {
+                    "label": "Printf",
+                    "insertTextMode": 1,
+                    "textEdit": {
+                        "newText": "Printf",
+                        "range": {
+                            "start": { "line": 4, "character": 5 },
+                            "end": { "line": 4, "character": 7 },
+                        },
+                    },
+                },
+            ]
+        }))
+        .unwrap();
+
+        let list = response.handle_completion().unwrap();
+        assert_eq!(
+            list.items[0].insert_text_mode,
+            Some(InsertTextMode::AdjustIndentation)
+        );
+        assert_eq!(
+            list.items[0].text_edit,
+            Some(CompletionTextEdit::InsertReplace(InsertReplaceEdit {
+                new_text: "Println".to_string(),
+                insert: Range {
+                    start: Position::new(4, 5),
+                    end: Position::new(4, 7),
+                },
+                replace: Range {
+                    start: Position::new(4, 5),
+                    end: Position::new(4, 12),
+                },
+            }))
+        );
+        assert_eq!(list.items[1].insert_text_mode, Some(InsertTextMode::AsIs));
+        assert!(matches!(
+            list.items[1].text_edit,
+            Some(CompletionTextEdit::Edit(_))
+        ));
 
-        RequestMessage {
-            base_message: BaseMessage {
-                jsonrpc: "2.0".to_string(),
-            },
-            id: serde_json::Value::from(id),
-            method: "initialize".to_string(),
-            notification: 0,
-            params: serde_json::to_value(InitializeParams {
-                process_id,
-                root_uri,
-                client_info,
-                capabilities,
-                workspace_folders: Some(workspace_folders),
+        // Round trips through the same wire format
+        let item = serde_json::to_value(&list.items[0]).unwrap();
+        assert_eq!(item["insertTextMode"], json!(2));
+        assert_eq!(item["textEdit"]["replace"]["end"]["character"], json!(12));
+    }
+
+    #[test]
+    fn test_markdown_hover() {
+        let capabilities = ClientCapabilities::default()
+            .with_hover_content_format(vec!["markdown".to_string(), "plaintext".to_string()]);
+        assert_eq!(
+            serde_json::to_value(capabilities).unwrap()["textDocument"]["hover"],
+            json!({ "contentFormat": ["markdown", "plaintext"] })
+        );
+
+        let response: ResponseMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "result": {
+                "contents": { "kind": "markdown", "value": "```go\nfunc Println(a ...any)\n```" },
+                "range": {
+                    "start": { "line": 4, "character": 5 },
+                    "end": { "line": 4, "character": 12 },
+                },
+            }
+        }))
+        .unwrap();
+        let hover = response.handle_hover().unwrap().unwrap();
+        assert_eq!(
+            hover.contents,
+            HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: "```go\nfunc Println(a ...any)\n```".to_string(),
             })
-            .unwrap(),
+        );
+
+        let plaintext: ResponseMessage = serde_json::from_value(json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "result": { "contents": { "kind": "plaintext", "value": "func Println(a ...any)" } }
+        }))
+        .unwrap();
+        match plaintext.handle_hover().unwrap().unwrap().contents {
+            HoverContents::Markup(markup) => assert_eq!(markup.kind, MarkupKind::PlainText),
+            other => panic!("Expected markup content, got {:?}", other),
         }
     }
 
-    /// Helper function to create a new `textDocument/definition` request message.
-    /// id - The ID of the request message.
-    /// uri - The URI of the text document. (e.g. `file://path/to/code/main.go`)
-    /// line - The line number of the cursor position.
-    /// character - The the cursor position of the character we want to get the definition of.
-    pub fn new_get_definition(id: u32, uri: String, position: Position) -> Self {
-        RequestMessage {
-            base_message: BaseMessage {
-                jsonrpc: "2.0".to_string(),
-            },
-            id: serde_json::Value::from(id),
-            method: "textDocument/definition".to_string(),
-            notification: 0,
-            params: serde_json::json!({
-                "textDocument": {
-                    "uri": uri
-                },
-                "position": {
-                    "line": position.line,
-                    "character": position.character,
+    #[test]
+    fn test_did_change_full() {
+        let did_change = NotificationMessage::new_did_change_full(
+            "file:///code/main.go".to_string(),
+            4,
+            "package main\n".to_string(),
+        );
+        assert_eq!(
+            serde_json::to_value(did_change).unwrap(),
+            json!({
+                "jsonrpc": "2.0",
+                "method": "textDocument/didChange",
+                "params": {
+                    "textDocument": { "uri": "file:///code/main.go", "version": 4 },
+                    "contentChanges": [{ "text": "package main\n" }],
                 }
-            }),
-        }
+            })
+        );
     }
-}
 
-impl NotificationMessage {
-    /// Helper function to create a new `initialized` notification message.
-    /// This message is sent by the client to the server once it has finished initializing
-    /// and signals that the client is ready to receive requests.
-    pub fn new_initialized() -> Self {
-        NotificationMessage {
-            base_message: BaseMessage {
-                jsonrpc: "2.0".to_string(),
-            },
-            method: "initialized".to_string(),
-            params: serde_json::Value::Object(serde_json::Map::new()),
-        }
+    #[test]
+    fn test_versioned_text_document_identifiers() {
+        let did_change = NotificationMessage::new_did_change_full(
+            "file:///code/main.go".to_string(),
+            7,
+            String::new(),
+        );
+        let identifier: VersionedTextDocumentIdentifier =
+            serde_json::from_value(did_change.params["textDocument"].clone()).unwrap();
+        assert_eq!(identifier.version, 7);
+
+        // A versioned identifier requires a version, an edit may leave it out as `null`
+        let unversioned = json!({ "uri": "file:///code/main.go", "version": null });
+        assert!(
+            serde_json::from_value::<VersionedTextDocumentIdentifier>(unversioned.clone()).is_err()
+        );
+        let edit: OptionalVersionedTextDocumentIdentifier =
+            serde_json::from_value(unversioned).unwrap();
+        assert_eq!(edit.version, None);
     }
-}
 
-impl ResponseMessage {
-    pub fn handle_initialize(&self) -> Result<()> {
-        if self.error.is_some() {
-            bail!("Error from LSP server: {:?}", self.error);
-        };
+    #[test]
+    fn test_completion_resolve_support_capability() {
+        let capabilities = serde_json::to_value(ClientCapabilities::default()).unwrap();
+        assert_eq!(
+            capabilities["textDocument"]["completion"]["completionItem"],
+            json!({ "snippetSupport": true })
+        );
 
-        Ok(())
+        let capabilities = ClientCapabilities::default()
+            .with_completion_resolve_support(vec!["documentation".to_string()]);
+        assert_eq!(
+            serde_json::to_value(capabilities).unwrap()["textDocument"]["completion"]
+                ["completionItem"],
+            json!({
+                "snippetSupport": true,
+                "resolveSupport": { "properties": ["documentation"] },
+            })
+        );
     }
 
-    pub fn handle_definition(&self) -> Result<Vec<Location>> {
-        if self.error.is_some() {
-            bail!("Error from LSP server: {:?}", self.error);
+    #[test]
+    fn test_semantic_tokens_capability() {
+        let capabilities =
+            ClientCapabilities::default().with_semantic_tokens(SemanticTokensCapability {
+                token_types: vec!["function".to_string(), "string".to_string()],
+                token_modifiers: vec!["readonly".to_string()],
+                multiline_token_support: true,
+                ..SemanticTokensCapability::default()
+            });
+        assert_eq!(
+            serde_json::to_value(capabilities).unwrap()["textDocument"]["semanticTokens"],
+            json!({
+                "requests": { "range": true, "full": { "delta": true } },
+                "tokenTypes": ["function", "string"],
+                "tokenModifiers": ["readonly"],
+                "formats": ["relative"],
+                "overlappingTokenSupport": false,
+                "multilineTokenSupport": true
+            })
+        );
+
+        let capabilities = serde_json::to_value(ClientCapabilities::default()).unwrap();
+        assert!(capabilities["textDocument"].get("semanticTokens").is_none());
+    }
+
+    #[test]
+    fn test_synchronization_capability() {
+        let capabilities =
+            ClientCapabilities::default().with_synchronization(SynchronizationCapability {
+                will_save_wait_until: true,
+                ..SynchronizationCapability::default()
+            });
+        assert_eq!(
+            serde_json::to_value(capabilities).unwrap()["textDocument"]["synchronization"],
+            json!({
+                "dynamicRegistration": false,
+                "willSave": true,
+                "willSaveWaitUntil": true,
+                "didSave": true
+            })
+        );
+    }
+
+    #[test]
+    fn test_document_symbol() {
+        let range = json!({
+            "start": { "line": 2, "character": 0 },
+            "end": { "line": 4, "character": 1 }
+        });
+        let selection_range = json!({
+            "start": { "line": 2, "character": 5 },
+            "end": { "line": 2, "character": 9 }
+        });
+        let nested = ResponseMessage::new_result(
+            json!(1),
+            json!([{
+                "name": "main",
+                "kind": 12,
+                "range": range,
+                "selectionRange": selection_range,
+                "children": [{
+                    "name": "x",
+                    "kind": 13,
+                    "range": range,
+                    "selectionRange": selection_range
+                }]
+            }]),
+        );
+        let DocumentSymbolResponse::Nested(symbols) = nested.handle_document_symbol().unwrap()
+        else {
+            panic!("expected the nested form");
         };
+        assert_eq!(symbols[0].selection_range, Range::new(2, 5, 2, 9));
+        assert_eq!(symbols[0].children.as_ref().unwrap()[0].name, "x");
 
-        if let Some(res) = &self.result {
-            if res.is_null() {
-                bail!("No definition found.");
-            }
-            let location: Result<Location, _> = serde_json::from_value(res.clone());
-            let locations: Result<Vec<Location>, _> = serde_json::from_value(res.clone());
+        let flat = ResponseMessage::new_result(
+            json!(2),
+            json!([{
+                "name": "main",
+                "kind": 12,
+                "location": { "uri": "file:///code/main.go", "range": range },
+                "containerName": "main"
+            }]),
+        );
+        assert!(matches!(
+            flat.handle_document_symbol().unwrap(),
+            DocumentSymbolResponse::Flat(_)
+        ));
 
-            match location {
-                Ok(loc) => Ok(vec![loc]),
-                Err(_) => match locations {
-                    Ok(locs) => Ok(locs),
-                    Err(_) => {
-                        anyhow::bail!("Failed to parse definition location(s) from response.")
-                    }
+        let capabilities =
+            ClientCapabilities::default().with_hierarchical_document_symbol_support(false);
+        assert_eq!(
+            serde_json::to_value(capabilities).unwrap()["textDocument"]["documentSymbol"],
+            json!({ "hierarchicalDocumentSymbolSupport": false })
+        );
+    }
+
+    #[test]
+    fn test_code_action_resolve_support_capability() {
+        let capabilities = ClientCapabilities::default()
+            .with_code_action_resolve_support(vec!["edit".to_string(), "command".to_string()]);
+        let code_action =
+            &serde_json::to_value(capabilities).unwrap()["textDocument"]["codeAction"];
+        assert_eq!(code_action["dataSupport"], json!(true));
+        assert_eq!(
+            code_action["resolveSupport"],
+            json!({ "properties": ["edit", "command"] })
+        );
+
+        let capabilities = ClientCapabilities::default().with_code_action_resolve_support(vec![]);
+        let code_action =
+            &serde_json::to_value(capabilities).unwrap()["textDocument"]["codeAction"];
+        assert!(code_action.get("dataSupport").is_none());
+        assert!(code_action.get("resolveSupport").is_none());
+    }
+
+    #[test]
+    fn test_serialization_is_deterministic() {
+        let build = || {
+            let capabilities = ClientCapabilities::default()
+                .with_markdown("marked", None)
+                .with_completion_resolve_support(vec![
+                    "documentation".to_string(),
+                    "detail".to_string(),
+                ])
+                .with_diagnostic_tag_support(vec![DiagnosticTag::Deprecated]);
+            let initialize = RequestMessage::new_initialize_with_capabilities(
+                1,
+                Some(42),
+                "file:///code".into(),
+                "snapshot".into(),
+                Some("0.1.0".into()),
+                vec![],
+                capabilities,
+            )
+            .with_extra(json!({ "initializationOptions": { "b": 1, "a": 2 } }));
+
+            let edit = |new_text: &str| TextEdit {
+                range: Range {
+                    start: Position::new(0, 0),
+                    end: Position::new(0, 0),
                 },
-            }
-        } else {
-            bail!("No definition found.");
-        }
+                new_text: new_text.to_string(),
+            };
+            let workspace_edit = WorkspaceEdit {
+                changes: Some(
+                    (0..16)
+                        .map(|i| (format!("file:///code/{}.go", i), vec![edit("x")]))
+                        .collect(),
+                ),
+                document_changes: None,
+            };
+            (
+                serde_json::to_vec(&initialize).unwrap(),
+                serde_json::to_vec(&workspace_edit).unwrap(),
+            )
+        };
+
+        assert_eq!(build(), build());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use serde_json::json;
+    #[test]
+    fn test_workspace_symbol_resolve_support_capability() {
+        let capabilities = serde_json::to_value(ClientCapabilities::default()).unwrap();
+        assert!(capabilities["workspace"].get("symbol").is_none());
+
+        let capabilities = ClientCapabilities::default()
+            .with_workspace_symbol_resolve_support(vec!["location.range".to_string()]);
+        assert_eq!(
+            serde_json::to_value(capabilities).unwrap()["workspace"]["symbol"],
+            json!({ "resolveSupport": { "properties": ["location.range"] } })
+        );
+    }
 
     #[test]
-    fn test_initialize_message() {
-        let process_id = std::process::id();
-        let expected_init_json = json!({
+    fn test_diagnostic_tags() {
+        let capabilities = ClientCapabilities::default().with_diagnostic_tag_support(vec![
+            DiagnosticTag::Unnecessary,
+            DiagnosticTag::Deprecated,
+        ]);
+        assert_eq!(
+            serde_json::to_value(capabilities).unwrap()["textDocument"]["publishDiagnostics"],
+            json!({ "tagSupport": { "valueSet": [1, 2] } })
+        );
+
+        let notification: NotificationMessage = serde_json::from_value(json!({
             "jsonrpc": "2.0",
-            "id": 1,
-            "notification": 0,
-            "method": "initialize",
+            "method": "textDocument/publishDiagnostics",
             "params": {
-                "processId": process_id,
-                "clientInfo": {
-                    "name": "YourLSPClientName", // Customize this
-                    "version": "1.0.0" // Optional: Adjust as necessary
-                },
-                "rootUri": "file://path/to/root",
-                "capabilities": {
-                    "workspace": {
-                        "workspaceFolders": true,
-                        "didChangeConfiguration": {
-                            "dynamicRegistration": true
-                        },
-                        "workspaceEdit": {
-                            "documentChanges": true
-                        },
-                        "configuration": true
+                "uri": "file:///code/main.go",
+                "diagnostics": [{
+                    "range": {
+                        "start": { "line": 2, "character": 7 },
+                        "end": { "line": 2, "character": 12 },
                     },
-                    "textDocument": {
-                        "hover": {
-                            "contentFormat": ["plaintext"]
-                        },
-                        "completion": {
-                            "completionItem": {
-                                "snippetSupport": true // Set to false if your client does not support snippets
-                            }
-                        },
-                        "codeAction": {
-                            "codeActionLiteralSupport": {
-                                "codeActionKind": {
-                                    "valueSet": ["source.organizeImports", "refactor.rewrite", "refactor.extract"]
-                                }
-                            }
-                        }
-                    }
-                },
-                "workspaceFolders": [{
-                    "uri": "file://path/to/workspace",
-                    "name": "file://path/to/workspace" ,
+                    "severity": 2,
+                    "message": "\"os\" imported and not used",
+                    "tags": [1],
                 }]
             }
+        }))
+        .unwrap();
+        match ServerNotification::from(notification) {
+            ServerNotification::PublishDiagnostics(params) => assert_eq!(
+                params.diagnostics[0].tags,
+                Some(vec![DiagnosticTag::Unnecessary])
+            ),
+            other => panic!("Expected diagnostics, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_diagnostic_related_information() {
+        let capabilities = ClientCapabilities::default()
+            .with_diagnostic_related_information(true)
+            .with_diagnostic_tag_support(vec![DiagnosticTag::Deprecated]);
+        assert_eq!(
+            serde_json::to_value(capabilities).unwrap()["textDocument"]["publishDiagnostics"],
+            json!({ "relatedInformation": true, "tagSupport": { "valueSet": [2] } })
+        );
+
+        let diagnostic: Diagnostic = serde_json::from_value(json!({
+            "range": {
+                "start": { "line": 9, "character": 5 },
+                "end": { "line": 9, "character": 8 },
+            },
+            "severity": 1,
+            "message": "foo redeclared in this block",
+            "relatedInformation": [{
+                "location": {
+                    "uri": "file:///code/main.go",
+                    "range": {
+                        "start": { "line": 3, "character": 5 },
+                        "end": { "line": 3, "character": 8 },
+                    },
+                },
+                "message": "other declaration of foo",
+            }]
+        }))
+        .unwrap();
+        let related = &diagnostic.related_information.unwrap()[0];
+        assert_eq!(related.location.uri, "file:///code/main.go");
+        assert_eq!(related.location.range.start, Position::new(3, 5));
+    }
+
+    #[test]
+    fn test_group_diagnostics_by_severity() {
+        let diagnostic = |severity: Option<u8>, message: &str| -> Diagnostic {
+            serde_json::from_value(json!({
+                "range": {
+                    "start": { "line": 0, "character": 0 },
+                    "end": { "line": 0, "character": 1 },
+                },
+                "severity": severity,
+                "message": message,
+            }))
+            .unwrap()
+        };
+        let diagnostics = vec![
+            diagnostic(Some(4), "hint"),
+            diagnostic(Some(2), "warning"),
+            diagnostic(None, "unspecified"),
+            diagnostic(Some(1), "error"),
+        ];
+
+        let groups = Diagnostic::group_by_severity(&diagnostics);
+        assert_eq!(
+            groups.keys().copied().collect::<Vec<_>>(),
+            vec![
+                DiagnosticSeverity::Error,
+                DiagnosticSeverity::Warning,
+                DiagnosticSeverity::Hint
+            ]
+        );
+        let errors: Vec<&str> = groups[&DiagnosticSeverity::Error]
+            .iter()
+            .map(|diagnostic| diagnostic.message.as_str())
+            .collect();
+        assert_eq!(errors, vec!["unspecified", "error"]);
+        assert_eq!(groups[&DiagnosticSeverity::Warning].len(), 1);
+    }
+
+    #[test]
+    fn test_diagnostic_code_description() {
+        let diagnostic = json!({
+            "range": {
+                "start": { "line": 1, "character": 0 },
+                "end": { "line": 1, "character": 4 },
+            },
+            "code": "S1000",
+            "codeDescription": { "href": "https://staticcheck.dev/docs/checks#S1000" },
+            "message": "should use a simple channel send/receive instead of select",
         });
+        let parsed: Diagnostic = serde_json::from_value(diagnostic.clone()).unwrap();
+        assert_eq!(
+            parsed.code_description,
+            Some(CodeDescription {
+                href: "https://staticcheck.dev/docs/checks#S1000".to_string()
+            })
+        );
+        assert_eq!(serde_json::to_value(parsed).unwrap(), diagnostic);
+    }
 
-        let init_params = RequestMessage::new_initialize(
+    #[test]
+    fn test_with_extra_params() {
+        let completion = RequestMessage::new_completion(
             1,
-            process_id,
-            "file://path/to/root".to_string(),
-            "YourLSPClientName".to_string(),
-            "1.0.0".to_string(),
-            vec![WorkspaceFolder {
-                uri: "file://path/to/workspace".to_string(),
-                name: "file://path/to/workspace".to_string(),
-            }],
+            "file:///code/main.go".to_string(),
+            Position::new(4, 8),
+            None,
+        )
+        .with_extra(json!({ "limit": 20 }));
+        assert_eq!(completion.params["limit"], 20);
+        assert_eq!(
+            completion.params["position"],
+            json!({ "line": 4, "character": 8 })
         );
 
-        // Check that the JSON serialization is correct
-        let init_params_json = serde_json::to_value(init_params).unwrap();
-        assert_eq!(expected_init_json, init_params_json);
+        let shutdown = RequestMessage::new_shutdown(2).with_extra(json!({ "force": true }));
+        assert_eq!(shutdown.params, json!({ "force": true }));
     }
 
     #[test]
-    fn test_initialized_notification() {
-        let expected_initialized_json = json!({
-            "jsonrpc": "2.0",
-            "method": "initialized",
-            "params": {}
-        });
+    fn test_window_capabilities() {
+        let capabilities = ClientCapabilities::default()
+            .with_show_message(true)
+            .with_show_document(true);
+        assert_eq!(
+            serde_json::to_value(capabilities).unwrap()["window"],
+            json!({
+                "workDoneProgress": true,
+                "showMessage": { "messageActionItem": { "additionalPropertiesSupport": true } },
+                "showDocument": { "support": true },
+            })
+        );
 
-        let initialized_notification = NotificationMessage::new_initialized();
-        let initialized_notification_json = serde_json::to_value(initialized_notification).unwrap();
-        assert_eq!(expected_initialized_json, initialized_notification_json);
+        let capabilities = ClientCapabilities::default().with_work_done_progress(false);
+        assert_eq!(
+            serde_json::to_value(capabilities).unwrap()["window"],
+            json!({ "workDoneProgress": false })
+        );
     }
 
     #[test]
-    fn test_get_definition() {
-        let expected_get_definition_json = json!({
-            "jsonrpc": "2.0",
-            "id": 1,
-            "notification": 0,
-            "method": "textDocument/definition",
-            "params": {
-                "textDocument": {
-                    "uri": "file://path/to/code/main.go"
+    fn test_general_capabilities() {
+        let capabilities = serde_json::to_value(ClientCapabilities::default()).unwrap();
+        assert!(capabilities.get("general").is_none());
+
+        let capabilities = ClientCapabilities::default()
+            .with_markdown("marked", Some("1.1.0".to_string()))
+            .with_position_encodings(vec!["utf-8".to_string(), "utf-16".to_string()])
+            .with_stale_request_support(true, vec!["textDocument/semanticTokens/full".to_string()])
+            .with_regular_expressions("ECMAScript", Some("ES2020".to_string()));
+        assert_eq!(
+            serde_json::to_value(capabilities).unwrap()["general"],
+            json!({
+                "markdown": { "parser": "marked", "version": "1.1.0" },
+                "positionEncodings": ["utf-8", "utf-16"],
+                "staleRequestSupport": {
+                    "cancel": true,
+                    "retryOnContentModified": ["textDocument/semanticTokens/full"],
+                },
+                "regularExpressions": { "engine": "ECMAScript", "version": "ES2020" },
+            })
+        );
+    }
+
+    #[test]
+    fn test_range_new() {
+        assert_eq!(
+            Range::new(1, 2, 3, 4),
+            Range {
+                start: Position {
+                    line: 1,
+                    character: 2
+                },
+                end: Position {
+                    line: 3,
+                    character: 4
                 },
-                "position": {
-                    "line": 1,
-                    "character": 2
-                }
             }
-        });
+        );
+    }
 
-        let get_definition = RequestMessage::new_get_definition(
-            1,
-            "file://path/to/code/main.go".to_string(),
-            Position {
-                line: 1,
-                character: 2,
-            },
+    #[test]
+    fn test_range_from_byte_range() {
+        // "é" is 2 bytes and 1 UTF-16 unit, "😀" is 4 bytes and 2 UTF-16 units
+        let text = "fn main() {}\nlet s = \"é😀\"; x";
+        let start = text.find('x').unwrap();
+        let expected = |character| Range {
+            start: Position::new(1, character),
+            end: Position::new(1, character + 1),
+        };
+        assert_eq!(
+            Range::from_byte_range(text, start, start + 1, PositionEncoding::Utf8).unwrap(),
+            expected(18)
+        );
+        assert_eq!(
+            Range::from_byte_range(text, start, start + 1, PositionEncoding::Utf16).unwrap(),
+            expected(15)
+        );
+        assert_eq!(
+            Range::from_byte_range(text, start, start + 1, PositionEncoding::Utf32).unwrap(),
+            expected(14)
         );
 
-        let get_definition_json = serde_json::to_value(get_definition).unwrap();
-        assert_eq!(expected_get_definition_json, get_definition_json);
+        let emoji = text.find('😀').unwrap();
+        assert!(
+            Range::from_byte_range(text, emoji + 1, emoji + 4, PositionEncoding::Utf8).is_err()
+        );
+        assert!(Range::from_byte_range(text, 0, text.len() + 1, PositionEncoding::Utf8).is_err());
+        assert!(Range::from_byte_range(text, 2, 1, PositionEncoding::Utf8).is_err());
+
+        let result: InitializeResult =
+            serde_json::from_value(json!({ "capabilities": { "positionEncoding": "utf-8" } }))
+                .unwrap();
+        assert_eq!(
+            result.capabilities.position_encoding,
+            Some(PositionEncoding::Utf8)
+        );
     }
 }
@@ -0,0 +1,302 @@
+//! A scriptable in-memory LSP server for integration tests.
+
+use crate::client::{Direction, LoggedMessage, LspClient};
+use crate::framing::{ContentLength, Framing};
+use crate::protocol::{LspErrorCode, RequestId, ResponseError, ResponseMessage};
+use anyhow::{anyhow, Result};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncWriteExt, BufReader};
+
+type Answer = std::result::Result<serde_json::Value, ResponseError>;
+
+type Handler =
+    Box<dyn Fn(serde_json::Value) -> Result<serde_json::Value, ResponseError> + Send + Sync>;
+
+/// A mock server answering requests from a script of canned responses and handlers.
+///
+/// Requests are answered from, in order of priority:
+/// 1. the first scripted response for the request's method that was not used yet,
+/// 2. the handler registered for the method,
+/// 3. a default for `initialize` (empty capabilities) and `shutdown` (`null`),
+/// 4. otherwise a `MethodNotFound` error.
+///
+/// ```
+/// # use lsp_client_rs::testing::MockServer;
+/// # use lsp_client_rs::protocol::RequestMessage;
+/// # async fn example() -> anyhow::Result<()> {
+/// let mut client = MockServer::new()
+///     .expect("shutdown", serde_json::Value::Null)
+///     .client();
+/// client.send_request(RequestMessage::new_shutdown(1)).await?;
+/// client.handle_response().await?.handle_shutdown()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct MockServer {
+    script: VecDeque<(String, Answer)>,
+    handlers: HashMap<String, Handler>,
+    received: Arc<Mutex<Vec<serde_json::Value>>>,
+}
+
+impl MockServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scripts the result of the next request with the given method.
+    /// Responses scripted for the same method are consumed in the order they were added,
+    /// regardless of the responses scripted for other methods.
+    pub fn expect(mut self, method: impl Into<String>, result: serde_json::Value) -> Self {
+        self.script.push_back((method.into(), Ok(result)));
+        self
+    }
+
+    /// Scripts the responses of a session recorded with `LspClient::save_message_log`.
+    /// Every request the client sent is answered with the result or error the server
+    /// answered it with, in the recorded order. Messages the server sent on its own,
+    /// such as notifications, are not replayed.
+    pub fn from_recording(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let recording = std::fs::read_to_string(path)?;
+        let mut methods: HashMap<RequestId, String> = HashMap::new();
+        let mut server = Self::new();
+        for (index, line) in recording.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: LoggedMessage = serde_json::from_str(line).map_err(|e| {
+                anyhow!(
+                    "Invalid entry on line {} of {}: {}",
+                    index + 1,
+                    path.display(),
+                    e
+                )
+            })?;
+            let message = entry.message;
+            let id = message.get("id").and_then(RequestId::from_value);
+            let (Some(id), method) = (id, message["method"].as_str()) else {
+                continue;
+            };
+            match (entry.direction, method) {
+                (Direction::Sent, Some(method)) => {
+                    methods.insert(id, method.to_string());
+                }
+                (Direction::Received, None) => {
+                    let Some(method) = methods.remove(&id) else {
+                        continue;
+                    };
+                    let response: ResponseMessage = serde_json::from_value(message)?;
+                    let answer = match response.error {
+                        Some(error) => Err(error),
+                        None => Ok(response.result.unwrap_or_default()),
+                    };
+                    server.script.push_back((method, answer));
+                }
+                _ => {}
+            }
+        }
+        Ok(server)
+    }
+
+    /// Answers every request with the given method that is not scripted with `expect`.
+    pub fn on<H>(mut self, method: impl Into<String>, handler: H) -> Self
+    where
+        H: Fn(serde_json::Value) -> Result<serde_json::Value, ResponseError>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.handlers.insert(method.into(), Box::new(handler));
+        self
+    }
+
+    /// Returns every message the server has received so far, in order.
+    /// The returned list keeps filling up after the server was moved into `client`.
+    pub fn received(&self) -> Arc<Mutex<Vec<serde_json::Value>>> {
+        self.received.clone()
+    }
+
+    /// Starts the server on a background task and returns a client connected to it.
+    /// The server stops when the client sends `exit` or is dropped.
+    /// Must be called from within a tokio runtime.
+    pub fn client(self) -> LspClient {
+        let (client_stream, server_stream) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(self.run(server_stream));
+        LspClient::from_stream(client_stream)
+    }
+
+    async fn run(mut self, stream: tokio::io::DuplexStream) {
+        let framing = ContentLength::default();
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut reader = BufReader::new(reader);
+
+        while let Ok(body) = framing.decode(&mut reader).await {
+            let Ok(message) = serde_json::from_slice::<serde_json::Value>(&body) else {
+                continue;
+            };
+            self.received.lock().unwrap().push(message.clone());
+
+            let method = message["method"].as_str().unwrap_or_default().to_string();
+            let Some(id) = message.get("id").cloned() else {
+                if method == "exit" {
+                    return;
+                }
+                continue;
+            };
+            if method.is_empty() {
+                // A response to a request the server never sends.
+                continue;
+            }
+
+            let response = match self.answer(&method, message["params"].clone()) {
+                Ok(result) => ResponseMessage::new_result(id, result),
+                Err(error) => ResponseMessage::new_error(id, error),
+            };
+            let body = serde_json::to_vec(&response).unwrap();
+            let framed = framing.encode(&body).unwrap();
+            if writer.write_all(&framed).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    fn answer(&mut self, method: &str, params: serde_json::Value) -> Answer {
+        if let Some(index) = self
+            .script
+            .iter()
+            .position(|(expected, _)| expected == method)
+        {
+            if let Some((_, answer)) = self.script.remove(index) {
+                return answer;
+            }
+        }
+        if let Some(handler) = self.handlers.get(method) {
+            return handler(params);
+        }
+        match method {
+            "initialize" => Ok(serde_json::json!({ "capabilities": {} })),
+            "shutdown" => Ok(serde_json::Value::Null),
+            _ => Err(ResponseError::new(
+                LspErrorCode::MethodNotFound,
+                format!("Unhandled method {}", method),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{NotificationMessage, Position, RequestMessage};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_mock_server() {
+        let server = MockServer::new()
+            .expect("textDocument/definition", json!([]))
+            .on("textDocument/hover", |params| {
+                Ok(json!({ "contents": format!("line {}", params["position"]["line"]) }))
+            });
+        let received = server.received();
+        let mut client = server.client();
+
+        let initialize = RequestMessage::new_initialize(
+            1,
+            None,
+            "file:///tmp".into(),
+            "test".into(),
+            None,
+            vec![],
+        );
+        client.initialize(initialize).await.unwrap();
+
+        let uri = "file:///tmp/main.go".to_string();
+        let definition = RequestMessage::new_get_definition(2, uri.clone(), Position::new(0, 0));
+        let response = client.send_request_and_wait(definition).await.unwrap();
+        assert_eq!(response.result, Some(json!([])));
+
+        let hover = RequestMessage::new_hover(3, uri.clone(), Position::new(7, 0));
+        let response = client.send_request_and_wait(hover).await.unwrap();
+        assert_eq!(response.result, Some(json!({ "contents": "line 7" })));
+
+        // The scripted definition result was used up
+        let definition = RequestMessage::new_get_definition(4, uri, Position::new(0, 0));
+        let error = client.expect_error(definition).await.unwrap();
+        assert_eq!(error.code, LspErrorCode::MethodNotFound);
+
+        client
+            .send_request(NotificationMessage::new_exit())
+            .await
+            .unwrap();
+        // The server hangs up after `exit`
+        assert!(client.handle_response().await.is_err());
+        let methods: Vec<serde_json::Value> = received
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|message| message["method"].clone())
+            .collect();
+        assert_eq!(
+            methods,
+            vec![
+                "initialize",
+                "initialized",
+                "textDocument/definition",
+                "textDocument/hover",
+                "textDocument/definition",
+                "exit"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_script_out_of_order() {
+        let mut client = MockServer::new()
+            .expect("textDocument/hover", json!({ "contents": "first" }))
+            .expect("textDocument/definition", json!([]))
+            .expect("textDocument/hover", json!({ "contents": "second" }))
+            .client();
+        let uri = "file:///tmp/main.go".to_string();
+
+        // The client asks for the definition before the hover scripted ahead of it
+        let definition = RequestMessage::new_get_definition(1, uri.clone(), Position::new(0, 0));
+        let response = client.send_request_and_wait(definition).await.unwrap();
+        assert_eq!(response.result, Some(json!([])));
+        for (id, contents) in [(2, "first"), (3, "second")] {
+            let hover = RequestMessage::new_hover(id, uri.clone(), Position::new(0, 0));
+            let response = client.send_request_and_wait(hover).await.unwrap();
+            assert_eq!(response.result, Some(json!({ "contents": contents })));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_recording() {
+        let path = std::env::temp_dir().join(format!(
+            "lsp-client-rs-recording-{}.jsonl",
+            std::process::id()
+        ));
+        let uri = "file:///tmp/main.go".to_string();
+
+        let mut client = MockServer::new()
+            .expect("textDocument/hover", json!({ "contents": "func main()" }))
+            .client()
+            .with_message_log();
+        let hover = RequestMessage::new_hover(1, uri.clone(), Position::new(0, 5));
+        client.send_request_and_wait(hover).await.unwrap();
+        let definition = RequestMessage::new_get_definition(2, uri.clone(), Position::new(0, 5));
+        client.send_request_and_wait(definition).await.unwrap();
+        client.save_message_log(&path).unwrap();
+
+        let mut replayed = MockServer::from_recording(&path).unwrap().client();
+        std::fs::remove_file(&path).unwrap();
+        let hover = RequestMessage::new_hover(1, uri.clone(), Position::new(0, 5));
+        let response = replayed.send_request_and_wait(hover).await.unwrap();
+        assert_eq!(response.result, Some(json!({ "contents": "func main()" })));
+        // Recorded errors are replayed as well
+        let definition = RequestMessage::new_get_definition(2, uri, Position::new(0, 5));
+        let error = replayed.expect_error(definition).await.unwrap();
+        assert_eq!(error.code, LspErrorCode::MethodNotFound);
+    }
+}
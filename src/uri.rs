@@ -0,0 +1,162 @@
+use anyhow::{anyhow, bail, Result};
+use std::path::{Path, PathBuf};
+
+/// Builds a `file://` URI from a filesystem path, percent-encoding any byte that is not
+/// allowed in a URI path. On Windows, `C:\code\main.go` becomes `file:///C:/code/main.go`.
+/// The path is expected to be absolute.
+pub fn from_path(path: &Path) -> String {
+    let bytes = path.as_os_str().as_encoded_bytes();
+    let bytes: Vec<u8> = if cfg!(windows) {
+        bytes
+            .iter()
+            .map(|&byte| if byte == b'\\' { b'/' } else { byte })
+            .collect()
+    } else {
+        bytes.to_vec()
+    };
+
+    let mut uri = String::from("file://");
+    if bytes.first() != Some(&b'/') {
+        uri.push('/');
+    }
+    for byte in bytes {
+        if byte.is_ascii_alphanumeric() || b"-._~/:".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+/// Converts a `file://` URI back into a filesystem path, decoding percent-encoded bytes.
+/// Only local URIs are supported, i.e. with an empty or `localhost` authority.
+pub fn to_path(uri: &str) -> Result<PathBuf> {
+    let rest = uri
+        .strip_prefix("file://")
+        .ok_or_else(|| anyhow!("Not a file URI: {}", uri))?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => bail!("File URI has no path: {}", uri),
+    };
+    if !authority.is_empty() && authority != "localhost" {
+        bail!("Unsupported file URI authority '{}': {}", authority, uri);
+    }
+
+    let mut bytes = percent_decode(path)?;
+    if cfg!(windows) {
+        // `/C:/code` is a drive path, the leading slash is not part of it.
+        if bytes.len() >= 3
+            && bytes[0] == b'/'
+            && bytes[1].is_ascii_alphabetic()
+            && bytes[2] == b':'
+        {
+            bytes.remove(0);
+        }
+        for byte in bytes.iter_mut() {
+            if *byte == b'/' {
+                *byte = b'\\';
+            }
+        }
+    }
+
+    bytes_to_path(bytes)
+}
+
+/// Checks that `uri` looks like an absolute URI: a scheme such as `file` or `untitled`
+/// followed by `:`, and no whitespace or backslashes. `file://` URIs must also convert to a path.
+/// This catches typos like a plain path or a missing scheme, not every malformed URI.
+pub fn validate(uri: &str) -> Result<()> {
+    let scheme = match uri.split_once(':') {
+        Some((scheme, _)) => scheme,
+        None => bail!("URI has no scheme: {}", uri),
+    };
+    let mut chars = scheme.chars();
+    if !chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        || !chars.all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    {
+        bail!("Invalid URI scheme '{}': {}", scheme, uri);
+    }
+    if uri.contains(|c: char| c.is_whitespace() || c == '\\') {
+        bail!("URI contains whitespace or a backslash: {}", uri);
+    }
+    if scheme.eq_ignore_ascii_case("file") {
+        to_path(uri)?;
+    }
+    Ok(())
+}
+
+fn percent_decode(input: &str) -> Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(input.len());
+    let mut chars = input.bytes();
+    while let Some(byte) = chars.next() {
+        if byte != b'%' {
+            bytes.push(byte);
+            continue;
+        }
+        let hex = [
+            chars
+                .next()
+                .ok_or_else(|| anyhow!("Truncated escape in {}", input))?,
+            chars
+                .next()
+                .ok_or_else(|| anyhow!("Truncated escape in {}", input))?,
+        ];
+        if !hex.iter().all(u8::is_ascii_hexdigit) {
+            bail!("Invalid escape in {}", input);
+        }
+        bytes.push(u8::from_str_radix(std::str::from_utf8(&hex)?, 16)?);
+    }
+    Ok(bytes)
+}
+
+#[cfg(unix)]
+fn bytes_to_path(bytes: Vec<u8>) -> Result<PathBuf> {
+    use std::os::unix::ffi::OsStringExt;
+    Ok(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+}
+
+#[cfg(not(unix))]
+fn bytes_to_path(bytes: Vec<u8>) -> Result<PathBuf> {
+    Ok(PathBuf::from(String::from_utf8(bytes)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_from_path_encodes_special_characters() {
+        let path = Path::new("/home/me/my project/100%#main.go");
+        let uri = from_path(path);
+        assert_eq!(uri, "file:///home/me/my%20project/100%25%23main.go");
+        assert_eq!(to_path(&uri).unwrap(), path);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_from_path_windows_drive() {
+        let path = Path::new(r"C:\code\my project\main.go");
+        let uri = from_path(path);
+        assert_eq!(uri, "file:///C:/code/my%20project/main.go");
+        assert_eq!(to_path(&uri).unwrap(), path);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate("file:///code/main.go").is_ok());
+        assert!(validate("untitled:Untitled-1").is_ok());
+        assert!(validate("/code/main.go").is_err());
+        assert!(validate("C:\\code\\main.go").is_err());
+        assert!(validate("file:///code/my project").is_err());
+        assert!(validate("file://code/main.go").is_err());
+    }
+
+    #[test]
+    fn test_to_path_rejects_non_file_uris() {
+        assert!(to_path("https://example.com/main.go").is_err());
+        assert!(to_path("file://remote-host/main.go").is_err());
+        assert!(to_path("file:///bad%2").is_err());
+    }
+}